@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use crate::{command::Command, field::Field, vm::Operation, Ast, AstCommand};
+
+// past this many possible fields at one point, the field there is treated as unknown
+const MAX_STATES: usize = 32;
+
+// the fields a program could have at one point. values aren't tracked, so both sides of
+// every branch are possible
+#[derive(Clone, Debug, PartialEq)]
+enum States {
+    Known(Vec<Field>),
+    Unknown,
+}
+
+impl States {
+    fn union(self, other: States) -> States {
+        match (self, other) {
+            (States::Known(mut fields), States::Known(others)) => {
+                for field in others {
+                    if !fields.contains(&field) {
+                        fields.push(field);
+                    }
+                }
+                if fields.len() > MAX_STATES {
+                    States::Unknown
+                } else {
+                    States::Known(fields)
+                }
+            }
+            _ => States::Unknown,
+        }
+    }
+
+    fn contains(&self, other: &States) -> bool {
+        match (self, other) {
+            (States::Unknown, _) => true,
+            (States::Known(_), States::Unknown) => false,
+            (States::Known(fields), States::Known(others)) => {
+                others.iter().all(|field| fields.contains(field))
+            }
+        }
+    }
+}
+
+// what running a command does to a field known ahead of time
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resolved {
+    // the command and everything it pushed, as Field::commands_for returns them
+    pub commands: Vec<Command>,
+    pub before: Field,
+    pub after: Field,
+}
+
+// the possible fields before each command in the source, found by running every path
+// through the program without any values
+pub struct Analysis {
+    // by the offset of each command's color
+    states: HashMap<usize, States>,
+    // a command whose stone stays put, by the same offset
+    skipped: Option<usize>,
+}
+
+impl Analysis {
+    pub fn new(ast: &[Ast], field: &Field) -> Analysis {
+        let mut analysis = Analysis {
+            states: HashMap::new(),
+            skipped: None,
+        };
+        analysis.block(ast, States::Known(vec![field.clone()]));
+        analysis
+    }
+
+    // the same, as if one command didn't move its stone, to see whether anything depends
+    // on that move
+    pub(crate) fn without_move(ast: &[Ast], field: &Field, skipped: &AstCommand) -> Analysis {
+        let mut analysis = Analysis {
+            states: HashMap::new(),
+            skipped: Some(skipped.span().offset),
+        };
+        analysis.block(ast, States::Known(vec![field.clone()]));
+        analysis
+    }
+
+    fn block(&mut self, ast: &[Ast], mut states: States) -> States {
+        for node in ast {
+            states = self.node(node, states);
+        }
+        states
+    }
+
+    // purple commands jump whether or not their stone moves, so each branch starts from the
+    // fields after the purple command, blocked or not
+    fn node(&mut self, node: &Ast, states: States) -> States {
+        match node {
+            Ast::Normal { command } => self.command(*command, states),
+
+            Ast::PurpleUp {
+                begin,
+                body,
+                else_,
+                end,
+            } => {
+                let states = self.command(*begin, states);
+                let taken = self.block(body, states.clone());
+                let not_taken = match else_ {
+                    Some(else_) => {
+                        let states = self.command(else_.else_, states);
+                        self.block(&else_.body, states)
+                    }
+                    None => states,
+                };
+                self.command(*end, taken.union(not_taken))
+            }
+
+            // runs the body until the fields going into the head stop changing. the loop
+            // can only end right after the head moves
+            Ast::PurpleLeft { begin, body, end } => {
+                let mut entering = states;
+                loop {
+                    let checked = self.command(*begin, entering.clone());
+                    let body_end = self.block(body, checked.clone());
+                    let again = self.command(*end, body_end);
+                    if entering.contains(&again) {
+                        return checked;
+                    }
+                    entering = entering.union(again);
+                }
+            }
+        }
+    }
+
+    // records the fields before the command and returns the fields after it
+    fn command(&mut self, command: AstCommand, states: States) -> States {
+        let offset = command.span().offset;
+        let seen = self
+            .states
+            .remove(&offset)
+            .unwrap_or(States::Known(Vec::new()));
+        self.states.insert(offset, seen.union(states.clone()));
+        if self.skipped == Some(offset) {
+            return states;
+        }
+
+        match states {
+            States::Known(fields) => {
+                States::Known(fields.into_iter().fold(Vec::new(), |mut after, mut field| {
+                    field.commands_for(command.into(), &mut ());
+                    if !after.contains(&field) {
+                        after.push(field);
+                    }
+                    after
+                }))
+            }
+            States::Unknown => States::Unknown,
+        }
+    }
+
+    // every field the command might start from, or None if there are too many to know
+    pub fn fields_before(&self, command: &AstCommand) -> Option<&[Field]> {
+        match self.states.get(&command.span().offset)? {
+            States::Known(fields) => Some(fields),
+            States::Unknown => None,
+        }
+    }
+
+    // every different list of commands the command could run as, which is none if it's
+    // never reached, or None if the fields before it aren't known
+    pub(crate) fn outcomes(&self, command: &AstCommand) -> Option<Vec<Vec<Command>>> {
+        let fields = match self.states.get(&command.span().offset) {
+            Some(States::Known(fields)) => &fields[..],
+            Some(States::Unknown) => return None,
+            None => &[],
+        };
+        let mut outcomes = Vec::new();
+        for field in fields {
+            let ran = field.clone().commands_for((*command).into(), &mut ());
+            if !outcomes.contains(&ran) {
+                outcomes.push(ran);
+            }
+        }
+        Some(outcomes)
+    }
+
+    // what the command does, if there's only one field it could start from
+    pub fn resolve(&self, command: &AstCommand) -> Option<Resolved> {
+        resolve(self.fields_before(command)?, (*command).into())
+    }
+
+    // the same for each operation compiled from the source, by its span. operations from
+    // somewhere else are left to the vm
+    pub fn resolve_program(&self, program: &[Operation]) -> Vec<Option<Resolved>> {
+        program
+            .iter()
+            .map(|operation| {
+                let span = operation.span?;
+                match self.states.get(&span.offset)? {
+                    States::Known(fields) => resolve(fields, operation.command),
+                    States::Unknown => None,
+                }
+            })
+            .collect()
+    }
+}
+
+fn resolve(fields: &[Field], command: Command) -> Option<Resolved> {
+    let [before] = fields else {
+        return None;
+    };
+    let mut after = before.clone();
+    let commands = after.commands_for(command, &mut ());
+    Some(Resolved {
+        commands,
+        before: before.clone(),
+        after,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::Stone;
+
+    #[test]
+    fn straight_line() {
+        let (ast, _) = crate::parse("red right two red down one red down one yellow up").unwrap();
+        let analysis = Analysis::new(&ast, &Field::new());
+
+        let resolved = ast
+            .iter()
+            .map(|node| analysis.resolve(&node.command()).unwrap().commands)
+            .collect::<Vec<_>>();
+        // red down one is blocked by yellow, and yellow up pushes red
+        assert_eq!(resolved[2].len(), 0);
+        assert_eq!(
+            resolved[3]
+                .iter()
+                .map(|command| command.to_string())
+                .collect::<Vec<_>>(),
+            ["red up one (side effect)", "yellow up"]
+        );
+    }
+
+    #[test]
+    fn branches() {
+        // red moves in only one branch, so blue could start from either field
+        let (ast, _) = crate::parse("purple up red right one purple right blue up").unwrap();
+        let analysis = Analysis::new(&ast, &Field::new());
+        let blue = ast[1].command();
+        assert_eq!(analysis.fields_before(&blue).unwrap().len(), 2);
+        assert_eq!(analysis.resolve(&blue), None);
+
+        // but inside the branch it's known
+        let Ast::PurpleUp { body, .. } = &ast[0] else {
+            unreachable!()
+        };
+        let red = analysis.resolve(&body[0].command()).unwrap();
+        assert_eq!(red.after.position(Stone::Red), Some((2, 3)));
+
+        // red moves right each time around the loop, until green is in the way
+        let (ast, _) = crate::parse("purple left red right one purple right blue up").unwrap();
+        let analysis = Analysis::new(&ast, &Field::new());
+        assert_eq!(analysis.fields_before(&ast[1].command()).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn examples_resolve() {
+        for example in ["hello_world", "equality", "branching", "truth-machine"] {
+            let source = std::fs::read_to_string(format!("examples/{example}.stn")).unwrap();
+            let (ast, _) = crate::parse(&source).unwrap();
+            let program = crate::compile(&ast);
+            let resolved = Analysis::new(&ast, &Field::new()).resolve_program(&program);
+
+            // running with the resolved commands gives the same result
+            let mut vm = crate::vm::Vm::with_io(program.clone(), "0\n".as_bytes(), Vec::new());
+            let mut resolved_vm = crate::vm::Vm::with_io(program, "0\n".as_bytes(), Vec::new());
+            resolved_vm.set_resolved(resolved);
+            vm.run(&mut ()).unwrap();
+            resolved_vm.run(&mut ()).unwrap();
+            assert_eq!(vm.output(), resolved_vm.output(), "{example}");
+            assert_eq!(vm.field(), resolved_vm.field(), "{example}");
+        }
+    }
+
+    #[test]
+    fn blocked_purple_resolves() {
+        // the purple right ending the true branch is blocked, and still skips the else
+        let source = "#field .oy.. p.... r.... g...b #end red left three purple up red down one \
+                      blue up purple down red up one blue up purple right";
+        let (ast, field) = crate::parse(source).unwrap();
+        let field = field.unwrap();
+        let program = crate::compile(&ast);
+        let mut vm = crate::vm::Vm::with_io(program.clone(), std::io::empty(), Vec::new());
+        vm.set_field(field.clone());
+        vm.set_resolved(Analysis::new(&ast, &field).resolve_program(&program));
+        vm.run(&mut ()).unwrap();
+        assert_eq!(vm.output(), b"1");
+
+        // resolved for a different field than the vm has, so nothing is skipped
+        let mut vm = crate::vm::Vm::with_io(program.clone(), std::io::empty(), Vec::new());
+        vm.set_field(field);
+        vm.set_resolved(Analysis::new(&ast, &Field::new()).resolve_program(&program));
+        vm.run(&mut ()).unwrap();
+        assert_eq!(vm.output(), b"1");
+    }
+}
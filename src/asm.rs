@@ -0,0 +1,358 @@
+use std::{collections::HashMap, iter::Peekable};
+
+use crate::{
+    command::{Command, Dir, EitherNumber, OrangeNumber, RedNumber, Stone},
+    field::Field,
+    vm::{verify, Comparison, Math, Opcode, Operation},
+    Error, Span, Token, TokenKind,
+};
+
+// one instruction per line: the command as Command's Display writes it, then the opcode.
+// jumps name a label, which is defined on a line of its own, and continue at the
+// instruction after it. ; starts a comment, and a #field block works the same as in source
+// files
+//
+//     loop:
+//         purple left          jump_false done
+//         red up one           push_number 0
+//         purple right         jump_backward loop
+//     done:
+pub fn disassemble(program: &[Operation], field: Option<&Field>) -> String {
+    let mut out = String::new();
+
+    if let Some(field) = field {
+        out.push_str("#field\n");
+        for row in field.to_string().lines() {
+            out.push_str(&format!("    {row}\n"));
+        }
+        out.push_str("#end\n\n");
+    }
+
+    let mut targets = program
+        .iter()
+        .filter_map(|operation| jump_target(operation.opcode))
+        .collect::<Vec<_>>();
+    targets.sort();
+    targets.dedup();
+
+    for (i, operation) in program.iter().enumerate() {
+        if targets.contains(&i) {
+            out.push_str(&format!("L{i}:\n"));
+        }
+
+        let (mnemonic, operand) = mnemonic(operation.opcode);
+        let instruction = match operand {
+            Some(operand) => format!("{mnemonic} {operand}"),
+            None => mnemonic.to_string(),
+        };
+        out.push_str(&format!(
+            "    {:<32} {instruction:<24} ; {i}\n",
+            operation.command.to_string()
+        ));
+    }
+
+    // jumping to the end halts
+    for target in targets.iter().filter(|&&target| target >= program.len()) {
+        out.push_str(&format!("L{target}:\n"));
+    }
+
+    out
+}
+
+pub fn assemble(source: &str) -> Result<(Vec<Operation>, Option<Field>), Error> {
+    let field = crate::parse_field(source)?;
+
+    let mut program = Vec::new();
+    let mut labels = HashMap::new();
+    // (index, label, span) for each jump, resolved once every label is known
+    let mut jumps = Vec::new();
+
+    for line in lines(source) {
+        let mut words = line.into_iter().peekable();
+        let Some(&(first, first_span)) = words.peek() else {
+            continue;
+        };
+
+        if let Some(label) = first.strip_suffix(':') {
+            words.next();
+            if !is_label(label) {
+                return Err(invalid(format!("invalid label {label:?}"), first_span));
+            }
+            if labels.insert(label, program.len()).is_some() {
+                return Err(invalid(format!("{label} is defined twice"), first_span));
+            }
+            if let Some((_, span)) = words.next() {
+                return Err(invalid(String::from("labels go on their own line"), span));
+            }
+            continue;
+        }
+
+        let command = command(&mut words, first_span)?;
+        let (name, name_span) = words
+            .next()
+            .ok_or_else(|| invalid(String::from("expected an opcode"), first_span))?;
+        let operand = words.next();
+
+        let number = |operand: Option<(&str, Span)>| {
+            let (word, span) =
+                operand.ok_or_else(|| invalid(format!("{name} needs a number"), name_span))?;
+            word.parse::<i64>()
+                .map_err(|_| invalid(format!("expected a number, got {word:?}"), span))
+        };
+        let opcode = match name {
+            "push_number" => Opcode::PushNumber(number(operand)?),
+            "push_bool" => Opcode::PushBool(match operand {
+                Some(("true", _)) => true,
+                Some(("false", _)) => false,
+                Some((word, span)) => {
+                    return Err(invalid(
+                        format!("expected true or false, got {word:?}"),
+                        span,
+                    ))
+                }
+                None => return Err(invalid(format!("{name} needs true or false"), name_span)),
+            }),
+            "jump_false" | "jump_forward" | "jump_backward" => {
+                let (target, span) =
+                    operand.ok_or_else(|| invalid(format!("{name} needs a label"), name_span))?;
+                jumps.push((program.len(), target, span));
+                match name {
+                    "jump_false" => Opcode::JumpFalse(0),
+                    "jump_forward" => Opcode::JumpForward(0),
+                    _ => Opcode::JumpBackward(0),
+                }
+            }
+            name => {
+                let opcode = OPCODES
+                    .iter()
+                    .find(|(mnemonic, _)| *mnemonic == name)
+                    .map(|(_, opcode)| *opcode)
+                    .ok_or_else(|| invalid(format!("unknown opcode {name:?}"), name_span))?;
+                if let Some((_, span)) = operand {
+                    return Err(invalid(format!("{name} doesn't take an operand"), span));
+                }
+                opcode
+            }
+        };
+
+        if let Some((_, span)) = words.next() {
+            return Err(invalid(String::from("too many words"), span));
+        }
+
+        program.push(Operation {
+            command,
+            opcode,
+            span: Some(first_span),
+        });
+    }
+
+    for (idx, label, span) in jumps {
+        let target = *labels
+            .get(label)
+            .ok_or_else(|| invalid(format!("undefined label {label:?}"), span))?;
+        program[idx].opcode = match program[idx].opcode {
+            Opcode::JumpFalse(_) => Opcode::JumpFalse(target),
+            Opcode::JumpForward(_) => Opcode::JumpForward(target),
+            _ => Opcode::JumpBackward(target),
+        };
+    }
+
+    // the same checks as loading a .stnc file, pointing at the line instead
+    if let Err(errors) = verify(&program) {
+        let first = &errors[0];
+        return Err(invalid(
+            first.to_string(),
+            program[first.ip()].span.unwrap(),
+        ));
+    }
+
+    Ok((program, field))
+}
+
+// opcodes without operands
+const OPCODES: [(&str, Opcode); 20] = [
+    ("start_array", Opcode::StartArray),
+    ("push_array", Opcode::PushArray),
+    ("end_array", Opcode::EndArray),
+    ("nth_array", Opcode::NthArray),
+    ("equal", Opcode::Comparison(Comparison::Equal)),
+    ("less_than", Opcode::Comparison(Comparison::LessThan)),
+    ("greater_than", Opcode::Comparison(Comparison::GreaterThan)),
+    ("quine", Opcode::Quine),
+    ("multiply", Opcode::Math(Math::Multiply)),
+    ("add", Opcode::Math(Math::Add)),
+    ("subtract", Opcode::Math(Math::Subtract)),
+    ("divide", Opcode::Math(Math::Divide)),
+    ("roll", Opcode::Roll),
+    ("dup", Opcode::Dup),
+    ("drop", Opcode::Drop),
+    ("not", Opcode::Not),
+    ("print", Opcode::Print),
+    ("input", Opcode::Input),
+    ("printc", Opcode::Printc),
+    ("swap", Opcode::Swap),
+];
+
+fn mnemonic(opcode: Opcode) -> (&'static str, Option<String>) {
+    match opcode {
+        Opcode::PushNumber(num) => ("push_number", Some(num.to_string())),
+        Opcode::PushBool(bool) => ("push_bool", Some(bool.to_string())),
+        Opcode::JumpFalse(target) => ("jump_false", Some(format!("L{target}"))),
+        Opcode::JumpForward(target) => ("jump_forward", Some(format!("L{target}"))),
+        Opcode::JumpBackward(target) => ("jump_backward", Some(format!("L{target}"))),
+        Opcode::Die => ("die", None),
+        opcode => {
+            let (mnemonic, _) = OPCODES.iter().find(|(_, op)| *op == opcode).unwrap();
+            (mnemonic, None)
+        }
+    }
+}
+
+fn jump_target(opcode: Opcode) -> Option<usize> {
+    match opcode {
+        Opcode::JumpFalse(target) | Opcode::JumpForward(target) | Opcode::JumpBackward(target) => {
+            Some(target)
+        }
+        _ => None,
+    }
+}
+
+fn is_label(label: &str) -> bool {
+    let mut chars = label.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn invalid(why: String, span: Span) -> Error {
+    Error::InvalidAssembly { why, span }
+}
+
+// the words on each line, without comments or the #field block
+fn lines(source: &str) -> Vec<Vec<(&str, Span)>> {
+    let mut lines: Vec<Vec<(&str, Span)>> = Vec::new();
+    let mut last_line = 0;
+    let mut in_comment = false;
+    let mut in_field = false;
+
+    for (word, span) in crate::words(source) {
+        if span.line != last_line {
+            last_line = span.line;
+            in_comment = false;
+            lines.push(Vec::new());
+        }
+
+        match word {
+            "#field" => in_field = true,
+            "#end" => in_field = false,
+            _ if in_field || in_comment => {}
+            _ if word.starts_with(';') => in_comment = true,
+            _ => lines.last_mut().unwrap().push((word, span)),
+        }
+    }
+
+    lines
+}
+
+fn command<'a, I: Iterator<Item = (&'a str, Span)>>(
+    words: &mut Peekable<I>,
+    start: Span,
+) -> Result<Command, Error> {
+    let mut last = start;
+    let mut keyword = |what: &str| -> Result<Token, Error> {
+        let (word, span) = words
+            .next()
+            .ok_or_else(|| invalid(format!("expected {what}"), last))?;
+        last = span;
+        let kind = TokenKind::try_from(word)
+            .map_err(|_| invalid(format!("expected {what}, got {word:?}"), span))?;
+        Ok(Token { kind, span })
+    };
+
+    let color = Stone::try_from(keyword("a color")?)?;
+    let dir = Dir::try_from(keyword("a direction")?)?;
+    let number = match color {
+        Stone::Red => Some(EitherNumber::Red(RedNumber::try_from(keyword(
+            "a number",
+        )?)?)),
+        Stone::Orange => Some(EitherNumber::Orange(OrangeNumber::try_from(keyword(
+            "a number",
+        )?)?)),
+        _ => None,
+    };
+
+    // written the way Command's Display writes it
+    let side_effect = words.next_if(|(word, _)| *word == "(side").is_some();
+    if side_effect && words.next_if(|(word, _)| *word == "effect)").is_none() {
+        return Err(invalid(String::from("expected (side effect)"), last));
+    }
+
+    Ok(Command {
+        color,
+        dir,
+        number,
+        side_effect,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for example in [
+            "hello_world",
+            "equality",
+            "branching",
+            "truth-machine",
+            "quine",
+        ] {
+            let source = std::fs::read_to_string(format!("examples/{example}.stn")).unwrap();
+            let program = crate::compile(&crate::parse(&source).unwrap().0)
+                .into_iter()
+                .map(|op| Operation { span: None, ..op })
+                .collect::<Vec<_>>();
+
+            let asm = disassemble(&program, None);
+            let assembled = assemble(&asm).unwrap().0;
+            let assembled = assembled
+                .into_iter()
+                .map(|op| Operation { span: None, ..op })
+                .collect::<Vec<_>>();
+            assert_eq!(assembled, program, "{example}");
+        }
+    }
+
+    #[test]
+    fn labels() {
+        let (program, _) = assemble(
+            "; count down from 3
+            red right one   push_number 3
+            top:
+            purple left     jump_false done   ; loop head
+            red down one    push_number 1
+            yellow left     subtract
+            purple right    jump_backward top
+            done:",
+        )
+        .unwrap();
+
+        assert_eq!(program[1].opcode, Opcode::JumpFalse(5));
+        assert_eq!(program[4].opcode, Opcode::JumpBackward(1));
+
+        let (program, _) = assemble("red up one (side effect) push_number 0").unwrap();
+        assert!(program[0].command.side_effect);
+
+        let Err(Error::InvalidAssembly { why, span }) =
+            assemble("blue up print\nblue up jump_false nowhere\n")
+        else {
+            panic!("expected InvalidAssembly");
+        };
+        assert_eq!(
+            (why.as_str(), span.line),
+            ("undefined label \"nowhere\"", 2)
+        );
+    }
+}
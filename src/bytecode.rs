@@ -0,0 +1,346 @@
+use std::io::{Read, Write};
+
+use crate::{
+    command::{Command, Dir, EitherNumber, OrangeNumber, RedNumber, Stone},
+    field::Field,
+    vm::{verify, Comparison, Math, Opcode, Operation},
+    Error,
+};
+
+// .stnc files: the magic, a version byte, the field if the source had a #field block,
+// then the operations. numbers are little endian. spans aren't kept. version 1 had the old
+// jumps, where JumpFalse landed one past its target and an if's purple right didn't exist
+pub const MAGIC: &[u8; 4] = b"STNC";
+pub const VERSION: u8 = 2;
+
+pub fn write<W: Write>(
+    program: &[Operation],
+    field: Option<&Field>,
+    mut output: W,
+) -> Result<(), Error> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+
+    match field {
+        Some(field) => {
+            bytes.push(1);
+            bytes.extend((field.width() as u32).to_le_bytes());
+            bytes.extend((field.height() as u32).to_le_bytes());
+            bytes.extend(field.to_string().bytes().filter(|&b| b != b'\n'));
+        }
+        None => bytes.push(0),
+    }
+
+    bytes.extend((program.len() as u32).to_le_bytes());
+    for operation in program {
+        if operation.opcode == Opcode::Die {
+            return Err(Error::InvalidBytecode {
+                why: String::from("can't write an unpatched jump"),
+                offset: bytes.len(),
+            });
+        }
+        write_command(&mut bytes, operation.command);
+        write_opcode(&mut bytes, operation.opcode);
+    }
+
+    output.write_all(&bytes)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn write_command(bytes: &mut Vec<u8>, command: Command) {
+    bytes.push(Stone::ALL.iter().position(|&s| s == command.color).unwrap() as u8);
+    bytes.push(match command.dir {
+        Dir::Up => 0,
+        Dir::Down => 1,
+        Dir::Left => 2,
+        Dir::Right => 3,
+    });
+    bytes.push(command.number.map_or(0, |number| number.magnitude() as u8));
+    bytes.push(command.side_effect as u8);
+}
+
+fn write_opcode(bytes: &mut Vec<u8>, opcode: Opcode) {
+    match opcode {
+        Opcode::PushNumber(num) => {
+            bytes.push(0);
+            bytes.extend(num.to_le_bytes());
+        }
+        Opcode::PushBool(bool) => bytes.extend([1, bool as u8]),
+        Opcode::StartArray => bytes.push(2),
+        Opcode::PushArray => bytes.push(3),
+        Opcode::EndArray => bytes.push(4),
+        Opcode::NthArray => bytes.push(5),
+        Opcode::Comparison(comparison) => bytes.extend([6, comparison as u8]),
+        Opcode::Quine => bytes.push(7),
+        Opcode::Math(math) => bytes.extend([8, math as u8]),
+        Opcode::Roll => bytes.push(9),
+        Opcode::Dup => bytes.push(10),
+        Opcode::Drop => bytes.push(11),
+        Opcode::Not => bytes.push(12),
+        Opcode::Print => bytes.push(13),
+        Opcode::Input => bytes.push(14),
+        Opcode::Printc => bytes.push(15),
+        Opcode::Swap => bytes.push(16),
+        Opcode::JumpFalse(offset) => jump(bytes, 17, offset),
+        Opcode::JumpForward(offset) => jump(bytes, 18, offset),
+        Opcode::JumpBackward(offset) => jump(bytes, 19, offset),
+        Opcode::Die => unreachable!(),
+    }
+}
+
+fn jump(bytes: &mut Vec<u8>, tag: u8, offset: usize) {
+    bytes.push(tag);
+    bytes.extend((offset as u32).to_le_bytes());
+}
+
+pub fn read<R: Read>(mut input: R) -> Result<(Vec<Operation>, Option<Field>), Error> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let mut reader = Reader {
+        bytes: &bytes,
+        at: 0,
+    };
+
+    if reader.take(4)? != MAGIC {
+        return Err(reader.error(0, "not a stones bytecode file"));
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(reader.error(4, &format!("version {version}, expected {VERSION}")));
+    }
+
+    let field = match reader.u8()? {
+        0 => None,
+        1 => {
+            let width = reader.u32()? as usize;
+            let height = reader.u32()? as usize;
+            let start = reader.at;
+            let cells = reader.take(width.saturating_mul(height))?;
+            let layout = cells
+                .chunks(width.max(1))
+                .map(|row| String::from_utf8_lossy(row).into_owned())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let field =
+                Field::parse(&layout).map_err(|err| reader.error(start, &err.to_string()))?;
+            Some(field)
+        }
+        _ => return Err(reader.error(reader.at - 1, "bad field flag")),
+    };
+
+    let len = reader.u32()? as usize;
+    let mut program = Vec::new();
+    // where each operation starts, to point errors at it
+    let mut starts = Vec::new();
+    for _ in 0..len {
+        starts.push(reader.at);
+        let command = reader.command()?;
+        let opcode = reader.opcode()?;
+        program.push(Operation {
+            command,
+            opcode,
+            span: None,
+        });
+    }
+
+    if reader.at != bytes.len() {
+        return Err(reader.error(reader.at, "trailing bytes"));
+    }
+
+    if let Err(errors) = verify(&program) {
+        let first = &errors[0];
+        return Err(reader.error(starts[first.ip()], &first.to_string()));
+    }
+
+    Ok((program, field))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, offset: usize, why: &str) -> Error {
+        Error::InvalidBytecode {
+            why: why.to_string(),
+            offset,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8], Error> {
+        let bytes = self
+            .bytes
+            .get(self.at..self.at.saturating_add(len))
+            .ok_or_else(|| self.error(self.bytes.len(), "unexpected end of file"))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn command(&mut self) -> Result<Command, Error> {
+        let start = self.at;
+        let color = *Stone::ALL
+            .get(self.u8()? as usize)
+            .ok_or_else(|| self.error(start, "bad color"))?;
+        let dir = match self.u8()? {
+            0 => Dir::Up,
+            1 => Dir::Down,
+            2 => Dir::Left,
+            3 => Dir::Right,
+            _ => return Err(self.error(start + 1, "bad direction")),
+        };
+        let number = match (color, self.u8()?) {
+            (Stone::Red, 1) => Some(EitherNumber::Red(RedNumber::One)),
+            (Stone::Red, 2) => Some(EitherNumber::Red(RedNumber::Two)),
+            (Stone::Red, 3) => Some(EitherNumber::Red(RedNumber::Three)),
+            (Stone::Orange, 1) => Some(EitherNumber::Orange(OrangeNumber::One)),
+            (Stone::Orange, 2) => Some(EitherNumber::Orange(OrangeNumber::Two)),
+            (Stone::Red | Stone::Orange, _) => {
+                return Err(self.error(start + 2, "bad number"));
+            }
+            (_, 0) => None,
+            (_, _) => return Err(self.error(start + 2, &format!("{color} has no number"))),
+        };
+        let side_effect = match self.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(self.error(start + 3, "bad side effect flag")),
+        };
+
+        Ok(Command {
+            color,
+            dir,
+            number,
+            side_effect,
+        })
+    }
+
+    fn opcode(&mut self) -> Result<Opcode, Error> {
+        let start = self.at;
+        Ok(match self.u8()? {
+            0 => Opcode::PushNumber(i64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            1 => Opcode::PushBool(match self.u8()? {
+                0 => false,
+                1 => true,
+                _ => return Err(self.error(start + 1, "bad bool")),
+            }),
+            2 => Opcode::StartArray,
+            3 => Opcode::PushArray,
+            4 => Opcode::EndArray,
+            5 => Opcode::NthArray,
+            6 => Opcode::Comparison(match self.u8()? {
+                0 => Comparison::Equal,
+                1 => Comparison::LessThan,
+                2 => Comparison::GreaterThan,
+                _ => return Err(self.error(start + 1, "bad comparison")),
+            }),
+            7 => Opcode::Quine,
+            8 => Opcode::Math(match self.u8()? {
+                0 => Math::Multiply,
+                1 => Math::Add,
+                2 => Math::Subtract,
+                3 => Math::Divide,
+                _ => return Err(self.error(start + 1, "bad math operator")),
+            }),
+            9 => Opcode::Roll,
+            10 => Opcode::Dup,
+            11 => Opcode::Drop,
+            12 => Opcode::Not,
+            13 => Opcode::Print,
+            14 => Opcode::Input,
+            15 => Opcode::Printc,
+            16 => Opcode::Swap,
+            17 => Opcode::JumpFalse(self.u32()? as usize),
+            18 => Opcode::JumpForward(self.u32()? as usize),
+            19 => Opcode::JumpBackward(self.u32()? as usize),
+            tag => return Err(self.error(start, &format!("unknown opcode {tag}"))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(source: &str) -> Vec<u8> {
+        let (ast, field) = crate::parse(source).unwrap();
+        let program = crate::compile(&ast);
+
+        let mut bytes = Vec::new();
+        write(&program, field.as_ref(), &mut bytes).unwrap();
+        let (read_program, read_field) = read(bytes.as_slice()).unwrap();
+
+        let without_spans = program
+            .iter()
+            .map(|op| Operation { span: None, ..*op })
+            .collect::<Vec<_>>();
+        assert_eq!(read_program, without_spans);
+        assert_eq!(
+            read_field.map(|field| field.to_string()),
+            field.map(|field| field.to_string())
+        );
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        for example in [
+            "hello_world",
+            "equality",
+            "branching",
+            "truth-machine",
+            "quine",
+        ] {
+            let source = std::fs::read_to_string(format!("examples/{example}.stn")).unwrap();
+            round_trip(&source);
+        }
+        round_trip("#field\nb...o\n.r..g\n..y.p\n#end\nred down one blue up\n");
+    }
+
+    #[test]
+    fn rejects() {
+        let bytes = round_trip("red up one\npurple up\nblue up\npurple right\n");
+        let invalid = |bytes: &[u8]| match read(bytes) {
+            Err(Error::InvalidBytecode { why, offset }) => (why, offset),
+            other => panic!("expected InvalidBytecode, got {other:?}"),
+        };
+
+        assert_eq!(
+            invalid(&bytes[..bytes.len() - 1]).0,
+            "unexpected end of file"
+        );
+        assert_eq!(invalid(b"STNZ").0, "not a stones bytecode file");
+
+        // red up one, then a JumpFalse past the end
+        let mut far_jump = bytes.clone();
+        let jump = 5 + 1 + 4 + 13 + 4 + 1;
+        assert_eq!(far_jump[jump - 1], 17);
+        far_jump[jump..jump + 4].copy_from_slice(&100u32.to_le_bytes());
+        assert_eq!(
+            invalid(&far_jump),
+            (
+                String::from("JumpFalse(100) at 1 jumps outside the program"),
+                5 + 1 + 4 + 13
+            )
+        );
+
+        // blue up claiming to be a Dup
+        let mut mismatch = bytes.clone();
+        let print = 5 + 1 + 4 + 13 + 9 + 4;
+        assert_eq!(mismatch[print], 13);
+        mismatch[print] = 10;
+        assert_eq!(invalid(&mismatch).0, "`blue up` at 2 can't be Dup");
+
+        let mut die = Vec::new();
+        assert!(write(&[Operation::die()], None, &mut die).is_err());
+    }
+}
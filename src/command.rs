@@ -1,7 +1,7 @@
 use crate::{
     orange, red,
     vm::{Comparison, Math, Opcode},
-    AstCommand, Error, Token,
+    AstCommand, Error, Token, TokenKind,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -129,15 +129,7 @@ impl std::fmt::Display for Command {
         write!(
             f,
             "{} {}{}{}",
-            match self.color {
-                Stone::__ => "__",
-                Stone::Red => "red",
-                Stone::Orange => "orange",
-                Stone::Yellow => "yellow",
-                Stone::Green => "green",
-                Stone::Blue => "blue",
-                Stone::Purple => "purple",
-            },
+            self.color,
             match self.dir {
                 Dir::Up => "up",
                 Dir::Down => "down",
@@ -173,7 +165,53 @@ pub enum Stone {
     Purple,
 }
 
+impl std::fmt::Display for Stone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Stone::__ => "__",
+                Stone::Red => "red",
+                Stone::Orange => "orange",
+                Stone::Yellow => "yellow",
+                Stone::Green => "green",
+                Stone::Blue => "blue",
+                Stone::Purple => "purple",
+            }
+        )
+    }
+}
+
 impl Stone {
+    pub const ALL: [Stone; 6] = [
+        Stone::Red,
+        Stone::Orange,
+        Stone::Yellow,
+        Stone::Green,
+        Stone::Blue,
+        Stone::Purple,
+    ];
+
+    // the readme's field notation
+    pub fn letter(&self) -> char {
+        match self {
+            Stone::__ => '.',
+            Stone::Red => 'r',
+            Stone::Orange => 'o',
+            Stone::Yellow => 'y',
+            Stone::Green => 'g',
+            Stone::Blue => 'b',
+            Stone::Purple => 'p',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Stone> {
+        std::iter::once(Stone::__)
+            .chain(Stone::ALL)
+            .find(|stone| stone.letter() == letter)
+    }
+
     pub fn has_number(&self) -> bool {
         matches!(self, Stone::Red | Stone::Orange)
     }
@@ -190,13 +228,13 @@ impl Stone {
 impl TryFrom<Token> for Stone {
     type Error = Error;
     fn try_from(value: Token) -> Result<Stone, Self::Error> {
-        match value {
-            Token::Red => Ok(Stone::Red),
-            Token::Orange => Ok(Stone::Orange),
-            Token::Yellow => Ok(Stone::Yellow),
-            Token::Green => Ok(Stone::Green),
-            Token::Blue => Ok(Stone::Blue),
-            Token::Purple => Ok(Stone::Purple),
+        match value.kind {
+            TokenKind::Red => Ok(Stone::Red),
+            TokenKind::Orange => Ok(Stone::Orange),
+            TokenKind::Yellow => Ok(Stone::Yellow),
+            TokenKind::Green => Ok(Stone::Green),
+            TokenKind::Blue => Ok(Stone::Blue),
+            TokenKind::Purple => Ok(Stone::Purple),
             _ => Err(Error::ExpectedColor { got: value }),
         }
     }
@@ -213,11 +251,11 @@ pub enum Dir {
 impl TryFrom<Token> for Dir {
     type Error = Error;
     fn try_from(value: Token) -> Result<Self, Self::Error> {
-        match value {
-            Token::Left => Ok(Dir::Left),
-            Token::Right => Ok(Dir::Right),
-            Token::Up => Ok(Dir::Up),
-            Token::Down => Ok(Dir::Down),
+        match value.kind {
+            TokenKind::Left => Ok(Dir::Left),
+            TokenKind::Right => Ok(Dir::Right),
+            TokenKind::Up => Ok(Dir::Up),
+            TokenKind::Down => Ok(Dir::Down),
             _ => Err(Error::ExpectedDir { got: value }),
         }
     }
@@ -269,10 +307,10 @@ impl From<usize> for RedNumber {
 impl TryFrom<Token> for RedNumber {
     type Error = Error;
     fn try_from(value: Token) -> Result<Self, Self::Error> {
-        match value {
-            Token::One => Ok(RedNumber::One),
-            Token::Two => Ok(RedNumber::Two),
-            Token::Three => Ok(RedNumber::Three),
+        match value.kind {
+            TokenKind::One => Ok(RedNumber::One),
+            TokenKind::Two => Ok(RedNumber::Two),
+            TokenKind::Three => Ok(RedNumber::Three),
             _ => Err(Error::ExpectedRedNumber { got: value }),
         }
     }
@@ -306,9 +344,9 @@ impl From<usize> for OrangeNumber {
 impl TryFrom<Token> for OrangeNumber {
     type Error = Error;
     fn try_from(value: Token) -> Result<Self, Self::Error> {
-        match value {
-            Token::One => Ok(OrangeNumber::One),
-            Token::Two => Ok(OrangeNumber::Two),
+        match value.kind {
+            TokenKind::One => Ok(OrangeNumber::One),
+            TokenKind::Two => Ok(OrangeNumber::Two),
             _ => Err(Error::ExpectedOrangeNumber { got: value }),
         }
     }
@@ -0,0 +1,205 @@
+use std::ops::Range;
+
+use crate::{field::Field, Ast, AstCommand, Error, Span, TokenKind};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeKind {
+    Keyword(TokenKind),
+    // any other word, which is the other language in a polyglot
+    Word,
+    Whitespace,
+    // #field and #end
+    Pragma,
+    Row,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
+}
+
+// every byte of a source file, split into nodes. the keywords are the same tokens scan
+// produces, so anything in the ast can be found here by its span
+#[derive(Clone, Debug)]
+pub struct Cst {
+    source: String,
+    nodes: Vec<Node>,
+}
+
+impl Cst {
+    pub fn new(source: &str) -> Cst {
+        let mut nodes = Vec::new();
+        let mut in_field = false;
+        let mut at = Span {
+            offset: 0,
+            len: 0,
+            line: 1,
+            column: 1,
+        };
+
+        for (word, span) in crate::words(source) {
+            if span.offset > at.offset {
+                nodes.push(Node {
+                    kind: NodeKind::Whitespace,
+                    span: Span {
+                        len: span.offset - at.offset,
+                        ..at
+                    },
+                });
+            }
+
+            let kind = match word {
+                "#field" => {
+                    in_field = true;
+                    NodeKind::Pragma
+                }
+                "#end" => {
+                    in_field = false;
+                    NodeKind::Pragma
+                }
+                _ if in_field => NodeKind::Row,
+                _ => TokenKind::try_from(word).map_or(NodeKind::Word, NodeKind::Keyword),
+            };
+            nodes.push(Node { kind, span });
+
+            at = Span {
+                offset: span.offset + span.len,
+                len: 0,
+                line: span.line,
+                column: span.column + word.chars().count(),
+            };
+        }
+
+        if at.offset < source.len() {
+            nodes.push(Node {
+                kind: NodeKind::Whitespace,
+                span: Span {
+                    len: source.len() - at.offset,
+                    ..at
+                },
+            });
+        }
+
+        Cst {
+            source: source.to_string(),
+            nodes,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn text(&self, node: &Node) -> &str {
+        &self.source[node.span.offset..node.span.offset + node.span.len]
+    }
+
+    pub fn parse(&self) -> Result<(Vec<Ast>, Option<Field>), Error> {
+        crate::parse(&self.source)
+    }
+
+    // the node starting at offset, such as a token's
+    pub fn node_at(&self, offset: usize) -> Option<usize> {
+        self.nodes
+            .binary_search_by_key(&offset, |node| node.span.offset)
+            .ok()
+    }
+
+    // the nodes from a command's color to its last keyword, including any words between them
+    pub fn command(&self, command: &AstCommand) -> Range<usize> {
+        let last = command
+            .number
+            .map_or(command.dir_token, |number| number.number_token);
+        let start = self.node_at(command.color_token.span.offset).unwrap();
+        let end = self.node_at(last.span.offset).unwrap();
+        start..end + 1
+    }
+
+    // the source with some nodes replaced and every other byte as it was
+    pub fn replace(&self, nodes: Range<usize>, text: &str) -> String {
+        let start = self
+            .nodes
+            .get(nodes.start)
+            .map_or(self.source.len(), |node| node.span.offset);
+        let end = match self.nodes[nodes].last() {
+            Some(last) => last.span.offset + last.span.len,
+            None => start,
+        };
+        format!("{}{text}{}", &self.source[..start], &self.source[end..])
+    }
+}
+
+impl std::fmt::Display for Cst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for node in &self.nodes {
+            f.write_str(self.text(node))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lossless() {
+        for example in [
+            "hello_world",
+            "equality",
+            "branching",
+            "truth-machine",
+            "quine",
+            "errors",
+        ] {
+            let source = std::fs::read_to_string(format!("examples/{example}.stn")).unwrap();
+            let cst = Cst::new(&source);
+            assert_eq!(cst.to_string(), source, "{example}");
+
+            let keywords = cst
+                .nodes()
+                .iter()
+                .filter_map(|node| match node.kind {
+                    NodeKind::Keyword(kind) => Some(crate::Token {
+                        kind,
+                        span: node.span,
+                    }),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(keywords, crate::scan(&source).collect::<Vec<_>>());
+        }
+
+        assert_eq!(Cst::new("").to_string(), "");
+        assert_eq!(Cst::new("  \n\t").to_string(), "  \n\t");
+    }
+
+    #[test]
+    fn edits() {
+        let source = "int x; red  up /* */ one\n#field\nrob\nygp\n#end\nblue up  print it\n";
+        let cst = Cst::new(source);
+        let (ast, field) = cst.parse().unwrap();
+        assert!(field.is_some());
+
+        let red = cst.command(&ast[0].command());
+        let words = cst.nodes()[red.clone()]
+            .iter()
+            .map(|node| cst.text(node))
+            .collect::<String>();
+        assert_eq!(words, "red  up /* */ one");
+
+        let blue = cst.command(&ast[1].command());
+        assert_eq!(
+            cst.replace(blue, "blue left"),
+            "int x; red  up /* */ one\n#field\nrob\nygp\n#end\nblue left  print it\n"
+        );
+
+        let row = cst.nodes().iter().find(|node| node.kind == NodeKind::Row);
+        assert_eq!(row.map(|node| cst.text(node)), Some("rob"));
+    }
+}
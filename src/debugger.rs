@@ -0,0 +1,260 @@
+use std::io::{Read, Stdin, Stdout, Write};
+
+use crate::{
+    command::Stone,
+    vm::{StepResult, Vm},
+    Ast, Error, Span,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Breakpoint {
+    Line(usize),
+    Index(usize),
+    // the stone moved onto the row or column
+    Row(Stone, usize),
+    Column(Stone, usize),
+    Blocked,
+    PartiallyBlocked,
+    Pushed,
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "line {line}"),
+            Breakpoint::Index(idx) => write!(f, "bytecode index {idx}"),
+            Breakpoint::Row(stone, row) => write!(f, "{stone} enters row {row}"),
+            Breakpoint::Column(stone, col) => write!(f, "{stone} enters column {col}"),
+            Breakpoint::Blocked => write!(f, "a command is blocked"),
+            Breakpoint::PartiallyBlocked => write!(f, "a command is partially blocked"),
+            Breakpoint::Pushed => write!(f, "a command pushes another stone"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(usize),
+    Halted,
+}
+
+pub struct Debugger<R = Stdin, W = Stdout> {
+    vm: Vm<R, W>,
+    ast: Vec<Ast>,
+    breakpoints: Vec<Breakpoint>,
+    last_ip: Option<usize>,
+    // whether anything has run or the breakpoints at the start have been checked
+    started: bool,
+}
+
+impl<R: Read, W: Write> Debugger<R, W> {
+    pub fn new(vm: Vm<R, W>, ast: Vec<Ast>) -> Debugger<R, W> {
+        Debugger {
+            vm,
+            ast,
+            breakpoints: Vec::new(),
+            last_ip: None,
+            started: false,
+        }
+    }
+
+    pub fn vm(&self) -> &Vm<R, W> {
+        &self.vm
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(breakpoint);
+        self.breakpoints.len() - 1
+    }
+
+    pub fn delete_breakpoint(&mut self, idx: usize) -> Option<Breakpoint> {
+        (idx < self.breakpoints.len()).then(|| self.breakpoints.remove(idx))
+    }
+
+    // the span of the next operation to run
+    pub fn span(&self) -> Option<Span> {
+        self.vm.program().get(self.vm.ip())?.span
+    }
+
+    pub fn step(&mut self) -> Result<Stop, Error> {
+        let before = Stone::ALL.map(|stone| self.vm.field().position(stone));
+        let result = self.vm.step()?;
+        let after = Stone::ALL.map(|stone| self.vm.field().position(stone));
+
+        self.started = true;
+        let StepResult::Executed { ip, commands, .. } = &result else {
+            return Ok(Stop::Halted);
+        };
+        self.last_ip = Some(*ip);
+
+        let moved_onto = |stone: Stone, on: &dyn Fn((usize, usize)) -> bool| {
+            let idx = Stone::ALL.iter().position(|s| *s == stone).unwrap();
+            after[idx].is_some_and(on) && !before[idx].is_some_and(on)
+        };
+
+        let hit = self
+            .breakpoints
+            .iter()
+            .position(|breakpoint| match *breakpoint {
+                Breakpoint::Row(stone, row) => moved_onto(stone, &|(r, _)| r == row),
+                Breakpoint::Column(stone, col) => moved_onto(stone, &|(_, c)| c == col),
+                Breakpoint::Blocked => result.blocked(),
+                Breakpoint::PartiallyBlocked => result.partially_blocked(),
+                Breakpoint::Pushed => commands.iter().any(|(command, _)| command.side_effect),
+                Breakpoint::Line(_) | Breakpoint::Index(_) => false,
+            });
+
+        Ok(match hit {
+            Some(idx) => Stop::Breakpoint(idx),
+            None if self.vm.halted() => Stop::Halted,
+            None => Stop::Stepped,
+        })
+    }
+
+    // steps over the whole block if the next operation starts one
+    pub fn step_over(&mut self) -> Result<Stop, Error> {
+        if let Some(idx) = self.start_breakpoint() {
+            return Ok(Stop::Breakpoint(idx));
+        }
+        let Some(statement) = self.span().map(|span| self.statement(span)) else {
+            return self.step();
+        };
+
+        loop {
+            match self.step()? {
+                Stop::Stepped => {}
+                stop => return Ok(stop),
+            }
+
+            if let Some(idx) = self.location_breakpoint() {
+                return Ok(Stop::Breakpoint(idx));
+            }
+
+            match self.span() {
+                Some(span)
+                    if statement.offset <= span.offset
+                        && span.offset < statement.offset + statement.len => {}
+                _ => return Ok(Stop::Stepped),
+            }
+        }
+    }
+
+    pub fn continue_(&mut self) -> Result<Stop, Error> {
+        if let Some(idx) = self.start_breakpoint() {
+            return Ok(Stop::Breakpoint(idx));
+        }
+        loop {
+            match self.step()? {
+                Stop::Stepped => {}
+                stop => return Ok(stop),
+            }
+
+            if let Some(idx) = self.location_breakpoint() {
+                return Ok(Stop::Breakpoint(idx));
+            }
+        }
+    }
+
+    // nothing steps onto the first operation, so its breakpoints are checked once before the
+    // program starts
+    fn start_breakpoint(&mut self) -> Option<usize> {
+        if std::mem::replace(&mut self.started, true) {
+            return None;
+        }
+        self.location_breakpoint()
+    }
+
+    // line breakpoints only trigger on entering the line, not between commands on it
+    fn location_breakpoint(&self) -> Option<usize> {
+        let ip = self.vm.ip();
+        let line = self.span().map(|span| span.line);
+        let entered = match self.last_ip {
+            Some(last) if last + 1 == ip => {
+                self.vm.program()[last].span.map(|span| span.line) != line
+            }
+            _ => true,
+        };
+
+        self.breakpoints
+            .iter()
+            .position(|breakpoint| match *breakpoint {
+                Breakpoint::Line(at) => entered && line == Some(at),
+                Breakpoint::Index(at) => ip == at,
+                _ => false,
+            })
+    }
+
+    fn statement(&self, span: Span) -> Span {
+        fn find(ast: &[Ast], span: Span) -> Option<Span> {
+            ast.iter().find_map(|node| match node {
+                _ if node.command().span() == span => Some(node.span()),
+                Ast::PurpleUp { body, else_, .. } => find(body, span)
+                    .or_else(|| else_.as_ref().and_then(|else_| find(&else_.body, span))),
+                Ast::PurpleLeft { body, .. } => find(body, span),
+                Ast::Normal { .. } => None,
+            })
+        }
+
+        find(&self.ast, span).unwrap_or(span)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn debugger(source: &str) -> Debugger<std::io::Empty, std::io::Sink> {
+        let ast = crate::parse(source).unwrap().0;
+        let vm = Vm::with_io(crate::compile(&ast), std::io::empty(), std::io::sink());
+        Debugger::new(vm, ast)
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger =
+            debugger("red right two\nred down one\nred down one red up one\nyellow up\nblue up\n");
+        debugger.add_breakpoint(Breakpoint::Line(3));
+        debugger.add_breakpoint(Breakpoint::Blocked);
+        debugger.add_breakpoint(Breakpoint::Row(Stone::Red, 2));
+        debugger.add_breakpoint(Breakpoint::Index(5));
+
+        assert_eq!(debugger.continue_().unwrap(), Stop::Breakpoint(0));
+        assert_eq!(debugger.vm().ip(), 2);
+        assert_eq!(debugger.continue_().unwrap(), Stop::Breakpoint(1));
+        assert_eq!(debugger.vm().ip(), 3);
+        assert_eq!(debugger.continue_().unwrap(), Stop::Breakpoint(2));
+        assert_eq!(debugger.vm().ip(), 4);
+        assert_eq!(debugger.continue_().unwrap(), Stop::Breakpoint(3));
+        assert_eq!(debugger.continue_().unwrap(), Stop::Halted);
+    }
+
+    #[test]
+    fn breakpoints_at_start() {
+        let mut by_line = debugger("red up one\nblue up\n");
+        by_line.add_breakpoint(Breakpoint::Line(1));
+        assert_eq!(by_line.continue_().unwrap(), Stop::Breakpoint(0));
+        assert_eq!(by_line.vm().ip(), 0);
+        assert_eq!(by_line.continue_().unwrap(), Stop::Halted);
+
+        let mut by_index = debugger("red up one\nblue up\n");
+        by_index.add_breakpoint(Breakpoint::Index(0));
+        assert_eq!(by_index.step_over().unwrap(), Stop::Breakpoint(0));
+        assert_eq!(by_index.step_over().unwrap(), Stop::Stepped);
+        assert_eq!(by_index.vm().ip(), 1);
+    }
+
+    #[test]
+    fn next_over_block() {
+        let mut debugger = debugger(
+            "red left three\npurple up\n  red up one\n  red up one\npurple right\nblue up\n",
+        );
+        assert_eq!(debugger.step().unwrap(), Stop::Stepped);
+        assert_eq!(debugger.step_over().unwrap(), Stop::Stepped);
+        assert_eq!(debugger.vm().stack().len(), 2);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{Error, Span};
+
+pub fn render(error: &Error, name: &str, source: &str) -> String {
+    render_at(
+        "error",
+        &error.to_string(),
+        error.span(),
+        hint(error),
+        name,
+        source,
+    )
+}
+
+pub fn render_warning(
+    message: &str,
+    span: Span,
+    hint: Option<&str>,
+    name: &str,
+    source: &str,
+) -> String {
+    render_at("warning", message, Some(span), hint, name, source)
+}
+
+fn render_at(
+    level: &str,
+    message: &str,
+    span: Option<Span>,
+    hint: Option<&str>,
+    name: &str,
+    source: &str,
+) -> String {
+    let mut out = format!("{level}: {message}\n");
+
+    let Some(span) = span else {
+        if let Some(hint) = hint {
+            out.push_str(&format!("hint: {hint}\n"));
+        }
+        return out;
+    };
+
+    let line = source.lines().nth(span.line - 1).unwrap_or("");
+    let gutter = span.line.to_string().len();
+
+    // keep tabs so the carets line up with the source line
+    let padding = line
+        .chars()
+        .take(span.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let underline = source[span.offset..span.offset + span.len]
+        .chars()
+        .take_while(|&c| c != '\n')
+        .count()
+        .max(1);
+
+    out.push_str(&format!(
+        "{:gutter$}--> {name}:{}:{}\n",
+        "", span.line, span.column
+    ));
+    out.push_str(&format!("{:gutter$} |\n", ""));
+    out.push_str(&format!("{} | {line}\n", span.line));
+    out.push_str(&format!(
+        "{:gutter$} | {padding}{}\n",
+        "",
+        "^".repeat(underline)
+    ));
+    if let Some(hint) = hint {
+        out.push_str(&format!("{:gutter$} = hint: {hint}\n", ""));
+    }
+
+    out
+}
+
+fn hint(error: &Error) -> Option<&'static str> {
+    Some(match error {
+        Error::SyntaxError { .. } => {
+            "purple down goes inside purple up, and purple right closes purple up or purple left"
+        }
+        Error::UnexpectedEof => "the last command in the file is unfinished",
+        Error::UnterminatedBlock { .. } => "close the block with purple right",
+        Error::ExpectedColor { .. } => {
+            "commands start with red, orange, yellow, green, blue, or purple"
+        }
+        Error::ExpectedNumber { .. } => "red and orange commands end with a number",
+        Error::ExpectedRedNumber { .. } => "red moves one, two, or three spaces",
+        Error::ExpectedOrangeNumber { .. } => "orange moves one or two spaces",
+        Error::ExpectedDir { .. } => "a color is followed by up, down, left, or right",
+        Error::StackUnderflow => "there are fewer values on the stack than this command needs",
+        Error::IndexOutOfBounds { .. } => "array indices start at zero",
+        Error::NoArrayInProgress { .. } => "start an array with orange up one",
+        Error::InvalidInput { .. } => "input must be a number, true, or false",
+        Error::InvalidAssembly { .. } => {
+            "each line is a command and an opcode, like `red up one push_number 0`, or a label:"
+        }
+        Error::CantDesugar { .. } => "`stones expand` without --desugar shows each way it ran",
+        Error::QuineWithoutSource => "quines only print the source when run from a .stn file",
+        Error::InvalidBytecode { .. } => "recompile the program with --emit-bytecode",
+        Error::UnterminatedField { .. } => "close the field layout with #end",
+        Error::InvalidLayout { .. } => {
+            "fields use r, o, y, g, b, and p for each stone exactly once, and . for empty spaces"
+        }
+        _ => None?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stray_end() {
+        let source = "red up one\n\tpurple right    end\n";
+        let error = crate::parse(source).unwrap_err();
+        assert_eq!(
+            render(&error, "test.stn", source),
+            "error: purple Right without corresponding purple up or purple left
+ --> test.stn:2:2
+  |
+2 | \tpurple right    end
+  | \t^^^^^^^^^^^^
+  = hint: purple down goes inside purple up, and purple right closes purple up or purple left
+"
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use crate::{
+    analysis::Analysis,
+    command::{Command, Stone},
+    vm::{Opcode, StepResult, Vm},
+    Ast, AstCommand, Error,
+};
+
+// what a command ran as: anything it pushed, then itself, which is cut short or left out if
+// it was blocked
+pub type Ran = Vec<(Command, Opcode)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expanded {
+    // worked out ahead of time, since there's only one field the command can start from.
+    // there's one way for each operation compiled from it, like the purple right at the end
+    // of both branches of an if
+    Static(Vec<Ran>),
+    // each different way it ran when the program was run, in the order they first happened
+    Ran(Vec<Ran>),
+    // the field wasn't known and the run never got there
+    NotReached,
+}
+
+#[derive(Clone, Debug)]
+pub struct Expansion {
+    pub command: AstCommand,
+    pub expanded: Expanded,
+}
+
+// every command in the source, in order. the vm has the program compiled from the ast and
+// the field to start from, and is only run if some command's field isn't known. the run
+// stops after limit steps or at a runtime error, and anything it didn't reach is NotReached
+pub fn expand<R: Read, W: Write>(ast: &[Ast], vm: &mut Vm<R, W>, limit: usize) -> Vec<Expansion> {
+    let analysis = Analysis::new(ast, vm.field());
+    // purple doesn't have an opcode of its own, just the jumps it compiled to
+    let mut jumps = HashMap::<usize, Vec<Opcode>>::new();
+    for operation in vm.program() {
+        if let Some(span) = operation.span {
+            jumps.entry(span.offset).or_default().push(operation.opcode);
+        }
+    }
+
+    let mut commands = Vec::new();
+    flatten(ast, &mut commands);
+    let mut expansions = commands
+        .into_iter()
+        .map(|command| {
+            let expanded = match analysis.resolve(&command) {
+                Some(resolved) => {
+                    let mut ways = Vec::new();
+                    for &jump in &jumps[&command.span().offset] {
+                        let ran = resolved
+                            .commands
+                            .iter()
+                            .map(|&ran| (ran, ran.get_opcode().unwrap_or(jump)))
+                            .collect::<Ran>();
+                        if !ways.contains(&ran) {
+                            ways.push(ran);
+                        }
+                    }
+                    Expanded::Static(ways)
+                }
+                None => Expanded::NotReached,
+            };
+            Expansion { command, expanded }
+        })
+        .collect::<Vec<_>>();
+
+    if !expansions
+        .iter()
+        .any(|expansion| expansion.expanded == Expanded::NotReached)
+    {
+        return expansions;
+    }
+
+    let mut seen = HashMap::<usize, Vec<Ran>>::new();
+    for _ in 0..limit {
+        let Ok(StepResult::Executed {
+            operation,
+            commands,
+            ..
+        }) = vm.step()
+        else {
+            break;
+        };
+        if let Some(span) = operation.span {
+            let ways = seen.entry(span.offset).or_default();
+            if !ways.contains(&commands) {
+                ways.push(commands);
+            }
+        }
+    }
+    for expansion in &mut expansions {
+        if expansion.expanded == Expanded::NotReached {
+            if let Some(ways) = seen.remove(&expansion.command.span().offset) {
+                expansion.expanded = Expanded::Ran(ways);
+            }
+        }
+    }
+    expansions
+}
+
+fn flatten(ast: &[Ast], commands: &mut Vec<AstCommand>) {
+    for node in ast {
+        match node {
+            Ast::Normal { command } => commands.push(*command),
+
+            Ast::PurpleUp {
+                begin,
+                body,
+                else_,
+                end,
+            } => {
+                commands.push(*begin);
+                flatten(body, commands);
+                if let Some(else_) = else_ {
+                    commands.push(else_.else_);
+                    flatten(&else_.body, commands);
+                }
+                commands.push(*end);
+            }
+
+            Ast::PurpleLeft { begin, body, end } => {
+                commands.push(*begin);
+                flatten(body, commands);
+                commands.push(*end);
+            }
+        }
+    }
+}
+
+// the source with each command replaced by what it ran as, so every push is its own
+// command and blocked commands are gone. commands that never ran are left alone
+pub fn desugar(source: &str, ast: &[Ast], expansions: &[Expansion]) -> Result<String, Error> {
+    // the purple commands that run right after whatever comes before them in the source.
+    // the others are reached by a jump or run after either branch, so a push can't be
+    // written out in front of them
+    let mut in_line = Vec::new();
+    purple_in_line(ast, &mut in_line);
+
+    let mut desugared = source.to_string();
+    for expansion in expansions.iter().rev() {
+        let command = expansion.command;
+        let span = command.span();
+        let ways = match &expansion.expanded {
+            Expanded::Static(ways) | Expanded::Ran(ways) => ways,
+            Expanded::NotReached => continue,
+        };
+        // ways that only differ in the jump they ran are written the same
+        let mut written = Vec::new();
+        for ran in ways {
+            let commands = ran.iter().map(|(ran, _)| *ran).collect::<Vec<_>>();
+            if !written.contains(&commands) {
+                written.push(commands);
+            }
+        }
+        let [ran] = &written[..] else {
+            return Err(Error::CantDesugar {
+                why: format!(
+                    "`{}` runs {} different ways, so it can't be written out",
+                    Command::from(command),
+                    written.len()
+                ),
+                span,
+            });
+        };
+
+        // purple still jumps when it's blocked, so leaving it out would break up its block
+        if command.color == Stone::Purple && ran.iter().all(|ran| ran.side_effect) {
+            return Err(Error::CantDesugar {
+                why: format!(
+                    "`{}` is blocked, but it still jumps, so it can't be left out",
+                    Command::from(command)
+                ),
+                span,
+            });
+        }
+
+        let pushes = ran.iter().any(|ran| ran.side_effect);
+        if command.color == Stone::Purple && pushes && !in_line.contains(&span.offset) {
+            return Err(Error::CantDesugar {
+                why: format!(
+                    "`{}` pushes stones where nothing can be written before it",
+                    Command::from(command)
+                ),
+                span,
+            });
+        }
+
+        let text = ran
+            .iter()
+            .map(|ran| {
+                Command {
+                    side_effect: false,
+                    ..*ran
+                }
+                .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        desugared.replace_range(span.offset..span.offset + span.len, &text);
+    }
+    Ok(desugared)
+}
+
+fn purple_in_line(ast: &[Ast], in_line: &mut Vec<usize>) {
+    for node in ast {
+        match node {
+            Ast::Normal { .. } => {}
+
+            Ast::PurpleUp {
+                begin, body, else_, ..
+            } => {
+                in_line.push(begin.span().offset);
+                purple_in_line(body, in_line);
+                if let Some(else_) = else_ {
+                    purple_in_line(&else_.body, in_line);
+                }
+            }
+
+            Ast::PurpleLeft { body, end, .. } => {
+                in_line.push(end.span().offset);
+                purple_in_line(body, in_line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expand_source(source: &str) -> Vec<Expansion> {
+        let (ast, _) = crate::parse(source).unwrap();
+        let mut vm = Vm::with_io(crate::compile(&ast), std::io::empty(), std::io::sink());
+        expand(&ast, &mut vm, 1000)
+    }
+
+    fn commands(ran: &Ran) -> Vec<String> {
+        ran.iter().map(|(command, _)| command.to_string()).collect()
+    }
+
+    #[test]
+    fn expands() {
+        let expansions = expand_source("red right two red down one red down one yellow up");
+        let Expanded::Static(blocked) = &expansions[2].expanded else {
+            panic!("expected the field to be known");
+        };
+        assert_eq!(blocked, &[vec![]]);
+        let Expanded::Static(pushed) = &expansions[3].expanded else {
+            panic!("expected the field to be known");
+        };
+        assert_eq!(pushed.len(), 1);
+        assert_eq!(
+            commands(&pushed[0]),
+            ["red up one (side effect)", "yellow up"]
+        );
+
+        // red right three pushes false, so the outer if isn't taken. the inner if leaves two
+        // fields blue could start from, so it's never known or reached. the last blue up's
+        // field comes from the run
+        let expansions = expand_source(
+            "red right three purple up red up one purple up red down two purple right blue up purple right \
+             red right two blue up",
+        );
+        assert!(matches!(expansions[4].expanded, Expanded::Static(_)));
+        assert_eq!(expansions[6].expanded, Expanded::NotReached);
+        let Expanded::Ran(ways) = &expansions[9].expanded else {
+            panic!("expected blue to have run");
+        };
+        assert_eq!(ways.len(), 1);
+        assert_eq!(commands(&ways[0]), ["blue up"]);
+    }
+
+    #[test]
+    fn desugars() {
+        let source =
+            "red right two red down one\nred down one\nyellow up\nred right two red right three";
+        let (ast, _) = crate::parse(source).unwrap();
+        let expansions = expand_source(source);
+        let desugared = desugar(source, &ast, &expansions).unwrap();
+        assert_eq!(
+            desugared,
+            "red right two red down one\n\nred up one yellow up\nred right two red right one"
+        );
+
+        // it runs the same, without pushing anything
+        let run = |source: &str| {
+            let (ast, _) = crate::parse(source).unwrap();
+            let mut vm = Vm::with_io(crate::compile(&ast), std::io::empty(), std::io::sink());
+            vm.run(&mut ()).unwrap();
+            (vm.stack().to_vec(), vm.field().to_string())
+        };
+        assert_eq!(run(source), run(&desugared));
+        assert!(expand_source(&desugared).iter().all(|expansion| {
+            let Expanded::Static(ways) = &expansion.expanded else {
+                return false;
+            };
+            ways.len() == 1 && ways[0].len() == 1 && !ways[0][0].0.side_effect
+        }));
+
+        // the purple right closing an if with an else is compiled once for each branch, so
+        // it can run with two different jumps. it's still written once
+        let source = "red left three purple up purple down purple right";
+        let (ast, _) = crate::parse(source).unwrap();
+        let mut expansions = expand_source(source);
+        let end = Command::from(expansions[3].command);
+        expansions[3].expanded = Expanded::Ran(vec![
+            vec![(end, Opcode::JumpForward(3))],
+            vec![(end, Opcode::JumpForward(5))],
+        ]);
+        assert_eq!(desugar(source, &ast, &expansions).unwrap(), source);
+
+        // purple up is blocked, since its column is full
+        let source =
+            "#field y..gb o.... p.... .r... #end red right three purple up blue up purple right";
+        let (ast, field) = crate::parse(source).unwrap();
+        let mut vm = Vm::with_io(crate::compile(&ast), std::io::empty(), std::io::sink());
+        vm.set_field(field.unwrap());
+        let expansions = expand(&ast, &mut vm, 1000);
+        let Err(Error::CantDesugar { why, .. }) = desugar(source, &ast, &expansions) else {
+            panic!("expected the blocked purple up to stop it");
+        };
+        assert_eq!(
+            why,
+            "`purple up` is blocked, but it still jumps, so it can't be left out"
+        );
+    }
+}
@@ -1,54 +1,187 @@
-use crate::{Command, Dir, Stone};
+use crate::{
+    trace::{Event, Tracer},
+    Command, Dir, Error, Span, Stone,
+};
+
+#[derive(Clone)]
+pub struct Field {
+    field: Vec<Vec<Stone>>,
+    // stones that moved during the last command, including any that were pushed
+    moved: Vec<Stone>,
+}
 
-pub struct Field<const W: usize = 12, const H: usize = 6> {
-    field: [[Stone; W]; H],
+// the readme's notation, which Field::parse reads back
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in &self.field {
+            writeln!(f, "{}", row.iter().map(Stone::letter).collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+// one character per cell in terminal colors, with the stones that just moved highlighted
+pub struct Ansi<'a>(&'a Field);
+
+impl std::fmt::Display for Ansi<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in &self.0.field {
+            for stone in row {
+                let color = match stone {
+                    Stone::__ => "2",
+                    Stone::Red => "31",
+                    Stone::Orange => "38;5;208",
+                    Stone::Yellow => "33",
+                    Stone::Green => "32",
+                    Stone::Blue => "34",
+                    Stone::Purple => "35",
+                };
+                let highlight = if self.0.moved.contains(stone) {
+                    ";1;7"
+                } else {
+                    ""
+                };
+                write!(f, "\x1b[{color}{highlight}m{}\x1b[0m", stone.letter())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// the same stones in the same places, whatever moved last
+impl PartialEq for Field {
+    fn eq(&self, other: &Field) -> bool {
+        self.field == other.field
+    }
 }
 
-impl<const W: usize, const H: usize> std::fmt::Debug for Field<W, H> {
+impl std::fmt::Debug for Field {
     fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         // ┌┐└┘─│
-        writeln!(formatter, "┌{:─<width$}┐", "─", width = W * 10)?;
-        for row in self.field {
+        writeln!(formatter, "┌{:─<width$}┐", "─", width = self.width() * 10)?;
+        for row in &self.field {
             write!(formatter, "│")?;
             for col in row {
                 write!(formatter, "{:10}", format!("{col:?}"))?;
             }
             writeln!(formatter, "│")?;
         }
-        writeln!(formatter, "└{:─<width$}┘", "─", width = W * 10)
+        writeln!(formatter, "└{:─<width$}┘", "─", width = self.width() * 10)
     }
 }
 
-impl Field<12, 6> {
-    #[rustfmt::skip]
+impl Field {
     pub fn new() -> Field {
-        use Stone::*;
-
-        Field {
-            field: [
-                [Blue, __, __,   __, __,     __, Orange, __, __,    __, __,     __],
-                [__,   __, __,   __, __,     __, __,     __, __,    __, __,     __],
-                [__,   __, Red,  __, __,     __, __,     __, Green, __, __,     __],
-                [__,   __, __,   __, __,     __, __,     __, __,    __, __,     __],
-                [__,   __, __,   __, Yellow, __, __,     __, __,    __, Purple, __],
-                [__,   __, __,   __, __,     __, __,     __, __,    __, __,     __],
-            ],
+        Field::parse(
+            "
+            b.....o.....
+            ............
+            ..r.....g...
+            ............
+            ....y.....p.
+            ............
+            ",
+        )
+        .unwrap()
+    }
+
+    // the notation from the readme, one row per whitespace-separated word
+    pub fn parse(layout: &str) -> Result<Field, Error> {
+        Field::from_rows(crate::words(layout), None)
+    }
+
+    // rows are whitespace-separated words. at is where missing stones are reported
+    pub(crate) fn from_rows<'a>(
+        rows: impl Iterator<Item = (&'a str, Span)>,
+        at: Option<Span>,
+    ) -> Result<Field, Error> {
+        let invalid = |why: String, span| Err(Error::InvalidLayout { why, span });
+
+        let mut field: Vec<Vec<Stone>> = Vec::new();
+        let mut seen = Vec::new();
+        for (word, span) in rows {
+            let mut row = Vec::new();
+            for (i, (offset, c)) in word.char_indices().enumerate() {
+                let stone = match Stone::from_letter(c) {
+                    Some(stone) => stone,
+                    None => {
+                        let span = Span {
+                            offset: span.offset + offset,
+                            len: c.len_utf8(),
+                            column: span.column + i,
+                            ..span
+                        };
+                        return invalid(format!("unknown stone {c:?}"), Some(span));
+                    }
+                };
+
+                if stone != Stone::__ {
+                    if seen.contains(&stone) {
+                        return invalid(format!("more than one {stone} stone"), Some(span));
+                    }
+                    seen.push(stone);
+                }
+                row.push(stone);
+            }
+
+            if let Some(first) = field.first() {
+                if first.len() != row.len() {
+                    return invalid(
+                        format!("row is {} wide, expected {}", row.len(), first.len()),
+                        Some(span),
+                    );
+                }
+            }
+            field.push(row);
+        }
+
+        if let Some(missing) = Stone::ALL.iter().find(|stone| !seen.contains(stone)) {
+            return invalid(format!("no {missing} stone"), at);
+        }
+        // otherwise a stone could wrap around onto its own space
+        if field.len() < 2 || field[0].len() < 2 {
+            return invalid(
+                String::from("fields need at least two rows and two columns"),
+                at,
+            );
         }
+
+        Ok(Field {
+            field,
+            moved: Vec::new(),
+        })
+    }
+
+    pub fn ansi(&self) -> Ansi<'_> {
+        Ansi(self)
+    }
+
+    pub fn moved(&self) -> &[Stone] {
+        &self.moved
     }
-}
 
-#[allow(non_upper_case_globals)]
-impl<const Width: usize, const Height: usize> Field<Width, Height> {
     fn find(&self, color: Stone) -> (usize, usize) {
+        self.position(color).unwrap()
+    }
+
+    // (row, column) of a stone, if it's on the field
+    pub fn position(&self, color: Stone) -> Option<(usize, usize)> {
         for (row_idx, row) in self.field.iter().enumerate() {
             for (col_idx, my_color) in row.iter().enumerate() {
                 if *my_color == color {
-                    return (row_idx, col_idx);
+                    return Some((row_idx, col_idx));
                 }
             }
         }
 
-        unreachable!()
+        None
+    }
+
+    // whatever is in the next space over from a stone, which wraps around like a move does
+    pub fn next_to(&self, color: Stone, dir: Dir) -> Stone {
+        let (row, col) = self.find(color);
+        self.field[self.next_row(row, dir)][self.next_col(col, dir)]
     }
 
     fn set(&mut self, color: Stone, row: usize, col: usize) {
@@ -59,23 +192,23 @@ impl<const Width: usize, const Height: usize> Field<Width, Height> {
         self.field[row][col]
     }
 
-    pub const fn width(&self) -> usize {
-        Width
+    pub fn width(&self) -> usize {
+        self.field[0].len()
     }
 
-    pub const fn height(&self) -> usize {
-        Height
+    pub fn height(&self) -> usize {
+        self.field.len()
     }
 
-    fn next_row(row: usize, dir: Dir) -> usize {
+    fn next_row(&self, row: usize, dir: Dir) -> usize {
         if dir == Dir::Up {
             if row == 0 {
-                Height - 1
+                self.height() - 1
             } else {
                 row - 1
             }
         } else if dir == Dir::Down {
-            if row + 1 == Height {
+            if row + 1 == self.height() {
                 0
             } else {
                 row + 1
@@ -85,15 +218,15 @@ impl<const Width: usize, const Height: usize> Field<Width, Height> {
         }
     }
 
-    fn next_col(col: usize, dir: Dir) -> usize {
+    fn next_col(&self, col: usize, dir: Dir) -> usize {
         if dir == Dir::Left {
             if col == 0 {
-                Width - 1
+                self.width() - 1
             } else {
                 col - 1
             }
         } else if dir == Dir::Right {
-            if col + 1 == Width {
+            if col + 1 == self.width() {
                 0
             } else {
                 col + 1
@@ -103,22 +236,23 @@ impl<const Width: usize, const Height: usize> Field<Width, Height> {
         }
     }
 
-    pub fn commands_for(&mut self, cmd: Command, print_cmd: bool) -> Vec<Command> {
+    pub fn commands_for(&mut self, cmd: Command, tracer: &mut dyn Tracer) -> Vec<Command> {
         let mut cmds = Vec::new();
-        self.step_rec(&mut cmds, cmd, print_cmd);
+        self.moved.clear();
+        self.step_rec(&mut cmds, cmd, tracer);
         cmds
     }
 
     // returns whether the move was blocked
-    fn step_rec(&mut self, cmds: &mut Vec<Command>, cmd: Command, print_cmd: bool) -> bool {
+    fn step_rec(&mut self, cmds: &mut Vec<Command>, cmd: Command, tracer: &mut dyn Tracer) -> bool {
         let (row_idx, col_idx) = self.find(cmd.color);
         let (mut current_row, mut current_col) = (row_idx, col_idx);
 
         let mag = cmd.magnitude();
         let mut steps_taken = 0;
         for _ in 1..=mag {
-            let next_row = Self::next_row(current_row, cmd.dir);
-            let next_col = Self::next_col(current_col, cmd.dir);
+            let next_row = self.next_row(current_row, cmd.dir);
+            let next_col = self.next_col(current_col, cmd.dir);
             let next = self.get(next_row, next_col);
 
             if next > cmd.color {
@@ -134,19 +268,24 @@ impl<const Width: usize, const Height: usize> Field<Width, Height> {
                     dir: cmd.dir,
                     side_effect: true,
                 };
-                let blocked = self.step_rec(cmds, next_cmd, print_cmd);
+                let blocked = self.step_rec(cmds, next_cmd, tracer);
                 if blocked {
                     break;
                 }
-                if print_cmd {
-                    println!("pushed {cmd:?} > {next:?}");
-                }
+                tracer.trace(&Event::CommandPushed {
+                    command: next_cmd,
+                    by: cmd,
+                    field: self,
+                });
             } else {
                 unreachable!()
             }
 
             self.set(Stone::__, current_row, current_col);
             self.set(cmd.color, next_row, next_col);
+            if !self.moved.contains(&cmd.color) {
+                self.moved.push(cmd.color);
+            }
             current_row = next_row;
             current_col = next_col;
             steps_taken += 1;
@@ -154,9 +293,7 @@ impl<const Width: usize, const Height: usize> Field<Width, Height> {
 
         if steps_taken == 0 {
             // blocked completely, don't add any operations
-            if print_cmd {
-                println!("blocked {cmd:?}");
-            }
+            tracer.trace(&Event::CommandBlocked { command: cmd });
             true
         } else if steps_taken == mag {
             // fully successful, add our op
@@ -165,9 +302,11 @@ impl<const Width: usize, const Height: usize> Field<Width, Height> {
         } else if 1 <= steps_taken && steps_taken < mag {
             // partially successful, add partial op
             let new_cmd = cmd.change_magnitude(steps_taken);
-            if print_cmd {
-                println!("partially blocked {cmd:?} -> {new_cmd:?}");
-            }
+            tracer.trace(&Event::CommandPartiallyBlocked {
+                command: cmd,
+                old_magnitude: mag,
+                new_magnitude: steps_taken,
+            });
             cmds.push(new_cmd);
             false
         } else {
@@ -176,7 +315,7 @@ impl<const Width: usize, const Height: usize> Field<Width, Height> {
     }
 }
 
-impl Default for Field<12, 6> {
+impl Default for Field {
     fn default() -> Self {
         Self::new()
     }
@@ -185,7 +324,7 @@ impl Default for Field<12, 6> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{orange, red};
+    use crate::{orange, red, trace::PrintTracer};
 
     macro_rules! oplist {
         ($(($color:expr, $dir:ident, $number:expr, $side_effect:expr),)*) => {
@@ -206,17 +345,97 @@ mod test {
                 number: $number,
                 side_effect: false,
             };
-            let mut field = Field { field: $field };
+            let mut field = Field {
+                field: $field.iter().map(|row| row.to_vec()).collect(),
+                moved: Vec::new(),
+            };
 
             println!("before\n{field:?}");
-            let ops = field.commands_for(op, true);
+            let ops = field.commands_for(
+                op,
+                &mut PrintTracer {
+                    print_op: true,
+                    ..Default::default()
+                },
+            );
             println!("after\n{field:?}");
 
-            assert_eq!($expfield, field.field, "expected left, got right");
+            assert_eq!(
+                $expfield.iter().map(|row| row.to_vec()).collect::<Vec<_>>(),
+                field.field,
+                "expected left, got right"
+            );
             assert_eq!($expvm, ops, "expected left, got right");
         };
     }
 
+    #[test]
+    fn layout() {
+        let mut field = Field::parse("ro...\n.....\n..y..\n.g..b\n....p\n").unwrap();
+        assert_eq!((field.width(), field.height()), (5, 5));
+        assert_eq!(field.position(Stone::Blue), Some((3, 4)));
+
+        let ops = field.commands_for(
+            Command {
+                color: Stone::Red,
+                dir: Dir::Up,
+                number: red!(One),
+                side_effect: false,
+            },
+            &mut (),
+        );
+        assert_eq!(ops.len(), 1);
+        assert_eq!(field.position(Stone::Red), Some((4, 0)));
+
+        assert!(matches!(
+            Field::parse("ro..\n.y.\ngbp."),
+            Err(Error::InvalidLayout {
+                span: Some(Span { line: 2, .. }),
+                ..
+            })
+        ));
+        assert!(matches!(
+            Field::parse("rr.\noyg\nbp."),
+            Err(Error::InvalidLayout {
+                span: Some(Span { line: 1, .. }),
+                ..
+            })
+        ));
+        assert!(matches!(
+            Field::parse("roygb."),
+            Err(Error::InvalidLayout { span: None, .. })
+        ));
+        assert!(matches!(
+            Field::parse("roygbp"),
+            Err(Error::InvalidLayout { span: None, .. })
+        ));
+        assert!(matches!(
+            Field::parse("r\no\ny\ng\nb\np"),
+            Err(Error::InvalidLayout { span: None, .. })
+        ));
+    }
+
+    #[test]
+    fn render() {
+        let mut field = Field::parse("b.r.\no..y\ng.p.").unwrap();
+        assert_eq!(field.to_string(), "b.r.\no..y\ng.p.\n");
+
+        field.commands_for(
+            Command {
+                color: Stone::Blue,
+                dir: Dir::Right,
+                number: None,
+                side_effect: false,
+            },
+            &mut (),
+        );
+        assert_eq!(field.to_string(), ".br.\no..y\ng.p.\n");
+        assert_eq!(field.moved(), &[Stone::Blue]);
+
+        let ansi = field.ansi().to_string();
+        assert!(ansi.starts_with("\x1b[2m.\x1b[0m\x1b[34;1;7mb\x1b[0m\x1b[31mr\x1b[0m"));
+    }
+
     #[test]
     fn cmp() {
         assert!(Stone::__ < Stone::Red);
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use crate::{
+    command::Command,
+    cst::{Cst, NodeKind},
+    Ast, AstCommand, Error,
+};
+
+// trailing text starts at least this far in, like in the examples
+const COMMENT_COLUMN: usize = 24;
+const INDENT: &str = "    ";
+
+enum Line<'a> {
+    Command {
+        depth: usize,
+        command: String,
+        comment: Vec<&'a str>,
+    },
+    Text {
+        depth: usize,
+        text: &'a str,
+    },
+    // a #field block, kept as written
+    Raw(&'a str),
+    Blank,
+}
+
+// one command per line with block bodies indented. everything that isn't a keyword is kept,
+// since it's the other language in a polyglot: text on a command's line becomes its comment,
+// and text on a line of its own stays on a line of its own. keywords are only ever lowercase,
+// so `Red` is text and keeps its case
+pub fn format<'a>(source: &'a str) -> Result<String, Error> {
+    let (ast, _) = crate::parse(source)?;
+    let mut commands = Vec::new();
+    flatten(&ast, 0, &mut commands);
+
+    // the offset of every keyword, to the command it's part of
+    let mut keywords = HashMap::new();
+    for (i, (_, command)) in commands.iter().enumerate() {
+        keywords.insert(command.color_token.span.offset, i);
+        keywords.insert(command.dir_token.span.offset, i);
+        if let Some(number) = command.number {
+            keywords.insert(number.number_token.span.offset, i);
+        }
+    }
+
+    let mut lines = Vec::new();
+    // the command being written: its index, its line in the output, how many of its
+    // keywords have been seen, and the source line of the last one
+    let mut current: Option<(usize, usize, usize, usize)> = None;
+    // a run of text words on one source line: start, end, line
+    let mut run: Option<(usize, usize, usize)> = None;
+    let mut field_start = None;
+    let mut last_line = 0;
+
+    let flush = |run: &mut Option<(usize, usize, usize)>,
+                 lines: &mut Vec<Line<'a>>,
+                 current: Option<(usize, usize, usize, usize)>| {
+        let Some((start, end, line)) = run.take() else {
+            return;
+        };
+        let text = &source[start..end];
+        match current {
+            Some((i, at, seen, keyword_line))
+                if keyword_line == line || seen < keyword_count(&commands[i].1) =>
+            {
+                if let Line::Command { comment, .. } = &mut lines[at] {
+                    comment.push(text);
+                }
+            }
+            _ => {
+                let next = current.map_or(0, |(i, ..)| i + 1);
+                let depth = commands.get(next).map_or(0, |(depth, _)| *depth);
+                lines.push(Line::Text { depth, text });
+            }
+        }
+    };
+
+    let cst = Cst::new(source);
+    for node in cst.nodes() {
+        let span = node.span;
+        if node.kind == NodeKind::Whitespace {
+            continue;
+        }
+        if last_line != 0 && span.line > last_line + 1 && field_start.is_none() {
+            flush(&mut run, &mut lines, current);
+            if !matches!(lines.last(), None | Some(Line::Blank)) {
+                lines.push(Line::Blank);
+            }
+        }
+        last_line = span.line;
+
+        match node.kind {
+            NodeKind::Pragma if cst.text(node) == "#field" => {
+                flush(&mut run, &mut lines, current);
+                field_start = Some(span.offset);
+            }
+            // an #end without a #field is kept on its own line
+            NodeKind::Pragma => {
+                flush(&mut run, &mut lines, current);
+                let start = field_start.take().unwrap_or(span.offset);
+                lines.extend(
+                    source[start..span.offset + span.len]
+                        .lines()
+                        .map(|line| Line::Raw(line.trim_end())),
+                );
+            }
+            NodeKind::Row => {}
+
+            _ => match keywords.get(&span.offset) {
+                Some(&i) => {
+                    flush(&mut run, &mut lines, current);
+                    current = match current {
+                        Some((j, at, seen, _)) if j == i => Some((i, at, seen + 1, span.line)),
+                        _ => {
+                            let (depth, command) = commands[i];
+                            lines.push(Line::Command {
+                                depth,
+                                command: Command::from(command).to_string(),
+                                comment: Vec::new(),
+                            });
+                            Some((i, lines.len() - 1, 1, span.line))
+                        }
+                    };
+                }
+                None => match run {
+                    Some((start, _, line)) if line == span.line => {
+                        run = Some((start, span.offset + span.len, line));
+                    }
+                    _ => {
+                        flush(&mut run, &mut lines, current);
+                        run = Some((span.offset, span.offset + span.len, span.line));
+                    }
+                },
+            },
+        }
+    }
+    flush(&mut run, &mut lines, current);
+
+    let column = lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Command {
+                depth,
+                command,
+                comment,
+            } if !comment.is_empty() => Some(depth * INDENT.len() + command.len()),
+            _ => None,
+        })
+        .fold(COMMENT_COLUMN, usize::max);
+
+    let mut out = String::new();
+    for line in &lines {
+        match line {
+            Line::Command {
+                depth,
+                command,
+                comment,
+            } => {
+                let command = INDENT.repeat(*depth) + command;
+                if comment.is_empty() {
+                    out.push_str(&command);
+                } else {
+                    out.push_str(&format!("{command:<column$} {}", comment.join(" ")));
+                }
+            }
+            Line::Text { depth, text } => out.push_str(&(INDENT.repeat(*depth) + text)),
+            Line::Raw(text) => out.push_str(text),
+            Line::Blank => {}
+        }
+        out.push('\n');
+    }
+
+    // no blank lines at the end
+    while out.ends_with("\n\n") {
+        out.pop();
+    }
+    Ok(out)
+}
+
+// commands in source order along with how deep in blocks they are
+fn flatten(ast: &[Ast], depth: usize, commands: &mut Vec<(usize, AstCommand)>) {
+    for node in ast {
+        match node {
+            Ast::PurpleLeft { begin, body, end } => {
+                commands.push((depth, *begin));
+                flatten(body, depth + 1, commands);
+                commands.push((depth, *end));
+            }
+            Ast::PurpleUp {
+                begin,
+                body,
+                else_,
+                end,
+            } => {
+                commands.push((depth, *begin));
+                flatten(body, depth + 1, commands);
+                if let Some(else_) = else_ {
+                    commands.push((depth, else_.else_));
+                    flatten(&else_.body, depth + 1, commands);
+                }
+                commands.push((depth, *end));
+            }
+            Ast::Normal { command } => commands.push((depth, *command)),
+        }
+    }
+}
+
+fn keyword_count(command: &AstCommand) -> usize {
+    if command.number.is_some() {
+        3
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let source = "\
+Title Text
+red up one blue up   print it
+purple up  if
+red down
+two
+purple down
+  blue down green left
+purple right
+
+
+#field
+  b...o
+  .r..g
+  ..y.p
+#end
+";
+        assert_eq!(
+            format(source).unwrap(),
+            "\
+Title Text
+red up one
+blue up                  print it
+purple up                if
+    red down two
+purple down
+    blue down
+    green left
+purple right
+
+#field
+  b...o
+  .r..g
+  ..y.p
+#end
+"
+        );
+    }
+
+    #[test]
+    fn keeps_stray_end() {
+        let source = "red up one\n#end\nblue up\n";
+        assert_eq!(format(source).unwrap(), source);
+    }
+
+    #[test]
+    fn keeps_examples() {
+        for example in [
+            "hello_world",
+            "equality",
+            "branching",
+            "truth-machine",
+            "quine",
+        ] {
+            let source = std::fs::read_to_string(format!("examples/{example}.stn")).unwrap();
+            let formatted = format(&source).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted, "{example}");
+
+            // the same words in the same order, and the same program
+            let words = |source: &str| {
+                crate::words(source)
+                    .map(|(word, _)| word.to_string())
+                    .collect::<Vec<_>>()
+            };
+            let compiled = |source: &str| {
+                crate::compile(&crate::parse(source).unwrap().0)
+                    .into_iter()
+                    .map(|op| (op.command, op.opcode))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(words(&formatted), words(&source), "{example}");
+            assert_eq!(compiled(&formatted), compiled(&source), "{example}");
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::io::{Read, Stdin, Stdout, Write};
+
+use crate::{
+    command::{Command, Stone},
+    field::Field,
+    trace::{Event, Tracer},
+    vm::{Opcode, Vm},
+    Ast, AstCommand, Error, Value,
+};
+
+// runs the ast directly, moving stones the way the README describes. it's a reference to
+// check the compiler and vm against, not a faster way to run anything:
+//
+// - every command moves its stone when it's reached, purple ones included
+// - purple up moves, then pops a condition. if it's true the body runs, otherwise purple
+//   down moves and the else body runs. purple right moves after either
+// - purple left moves, then pops a condition. if it's true the body runs, purple right
+//   moves, and the loop starts over at purple left. if it's false the loop is done without
+//   reaching purple right
+// - a blocked purple command still pops and branches, as if it had moved
+pub struct Interpreter<R = Stdin, W = Stdout> {
+    // the stack, field, and io. its program is empty
+    vm: Vm<R, W>,
+    // how many commands have run, which stands in for the ip in errors
+    executed: usize,
+    limit: Option<usize>,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::with_io(std::io::stdin(), std::io::stdout())
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl<R: Read, W: Write> Interpreter<R, W> {
+    pub fn with_io(input: R, output: W) -> Interpreter<R, W> {
+        Interpreter {
+            vm: Vm::with_io(Vec::new(), input, output),
+            executed: 0,
+            limit: None,
+        }
+    }
+
+    // stop after this many commands, for programs that might not halt
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = Some(limit);
+    }
+
+    pub fn set_field(&mut self, field: Field) {
+        self.vm.set_field(field);
+    }
+
+    // returns false if it stopped at the limit
+    pub fn run(&mut self, ast: &[Ast], tracer: &mut dyn Tracer) -> Result<bool, Error> {
+        let finished = self.block(ast, tracer)?;
+        self.vm.output_mut().flush()?;
+        Ok(finished)
+    }
+
+    fn block(&mut self, ast: &[Ast], tracer: &mut dyn Tracer) -> Result<bool, Error> {
+        for node in ast {
+            if !self.node(node, tracer)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn node(&mut self, node: &Ast, tracer: &mut dyn Tracer) -> Result<bool, Error> {
+        match node {
+            Ast::Normal { command } => self.command(*command, tracer),
+
+            Ast::PurpleUp {
+                begin,
+                body,
+                else_,
+                end,
+            } => {
+                if !self.command(*begin, tracer)? {
+                    return Ok(false);
+                }
+                let finished = if self.vm.pop()?.is_truthy() {
+                    self.block(body, tracer)?
+                } else if let Some(else_) = else_ {
+                    self.command(else_.else_, tracer)? && self.block(&else_.body, tracer)?
+                } else {
+                    true
+                };
+                Ok(finished && self.command(*end, tracer)?)
+            }
+
+            Ast::PurpleLeft { begin, body, end } => loop {
+                if !self.command(*begin, tracer)? {
+                    return Ok(false);
+                }
+                if !self.vm.pop()?.is_truthy() {
+                    return Ok(true);
+                }
+                if !self.block(body, tracer)? || !self.command(*end, tracer)? {
+                    return Ok(false);
+                }
+            },
+        }
+    }
+
+    // moves the stone, running what it and anything it pushed does. purple's own action is
+    // the control flow around it
+    fn command(&mut self, command: AstCommand, tracer: &mut dyn Tracer) -> Result<bool, Error> {
+        if self.limit.is_some_and(|limit| self.executed >= limit) {
+            return Ok(false);
+        }
+        let ip = self.executed;
+        self.executed += 1;
+
+        for command in self.vm.field_mut().commands_for(command.into(), tracer) {
+            let Some(opcode) = command.get_opcode() else {
+                continue;
+            };
+            self.vm.execute(ip, command, opcode, tracer)?;
+            self.vm.trace_opcode(ip, command, opcode, tracer);
+        }
+
+        Ok(true)
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        self.vm.stack()
+    }
+
+    pub fn field(&self) -> &Field {
+        self.vm.field()
+    }
+
+    pub fn output(&self) -> &W {
+        self.vm.output()
+    }
+}
+
+// the state after each opcode, without purple's jumps since only the vm has those
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    command: Command,
+    opcode: Opcode,
+    field: String,
+    stack: Vec<Value>,
+    output: String,
+}
+
+#[derive(Default)]
+struct Snapshots {
+    snapshots: Vec<Snapshot>,
+    output: String,
+}
+
+impl Tracer for Snapshots {
+    fn trace(&mut self, event: &Event<'_>) {
+        match event {
+            Event::OutputWritten { text } => self.output.push_str(text),
+
+            Event::OpcodeExecuted {
+                command,
+                opcode,
+                field,
+                stack,
+                ..
+            } if command.color != Stone::Purple => self.snapshots.push(Snapshot {
+                command: *command,
+                opcode: *opcode,
+                field: field.to_string(),
+                stack: stack.to_vec(),
+                output: self.output.clone(),
+            }),
+
+            _ => {}
+        }
+    }
+}
+
+// runs source through the compiler and vm and through the interpreter, and describes the
+// first place they disagree. programs that don't halt are compared up to the limit
+pub fn first_divergence(source: &str, input: &str, limit: usize) -> Result<Option<String>, Error> {
+    let (ast, field) = crate::parse(source)?;
+
+    let mut vm = Vm::with_io(crate::compile(&ast), input.as_bytes(), std::io::sink());
+    let mut interpreter = Interpreter::with_io(input.as_bytes(), std::io::sink());
+    interpreter.set_limit(limit);
+    if let Some(field) = field {
+        vm.set_field(field.clone());
+        interpreter.set_field(field);
+    }
+
+    let mut vm_run = Snapshots::default();
+    let mut vm_result = Ok(());
+    for _ in 0..limit {
+        match vm.step_traced(&mut vm_run) {
+            Ok(result) if result != crate::vm::StepResult::Halted => {}
+            Ok(_) | Err(Error::Quine) => break,
+            Err(err) => {
+                vm_result = Err(err.to_string());
+                break;
+            }
+        }
+    }
+    let vm_finished = vm.halted() || vm_result.is_err();
+
+    let mut reference_run = Snapshots::default();
+    let (reference_finished, reference_result) = match interpreter.run(&ast, &mut reference_run) {
+        Ok(finished) => (finished, Ok(())),
+        Err(Error::Quine) => (true, Ok(())),
+        Err(err) => (true, Err(err.to_string())),
+    };
+
+    let (vm_run, reference_run) = (vm_run.snapshots, reference_run.snapshots);
+    for (i, (vm, reference)) in vm_run.iter().zip(&reference_run).enumerate() {
+        let differs = |what: &str, vm: &dyn std::fmt::Debug, reference: &dyn std::fmt::Debug| {
+            Some(format!(
+                "after {} opcodes, {what} differs: the vm has {vm:?}, the reference has {reference:?}",
+                i + 1
+            ))
+        };
+        if (vm.command, vm.opcode) != (reference.command, reference.opcode) {
+            return Ok(differs(
+                "the command",
+                &format!("{}: {:?}", vm.command, vm.opcode),
+                &format!("{}: {:?}", reference.command, reference.opcode),
+            ));
+        }
+        if vm.output != reference.output {
+            return Ok(differs("the output", &vm.output, &reference.output));
+        }
+        if vm.stack != reference.stack {
+            return Ok(differs("the stack", &vm.stack, &reference.stack));
+        }
+        if vm.field != reference.field {
+            return Ok(differs("the field", &vm.field, &reference.field));
+        }
+    }
+
+    if !vm_finished || !reference_finished {
+        return Ok(None);
+    }
+    if vm_run.len() != reference_run.len() {
+        return Ok(Some(format!(
+            "the vm ran {} opcodes, the reference ran {}",
+            vm_run.len(),
+            reference_run.len()
+        )));
+    }
+    if vm_result != reference_result {
+        return Ok(Some(format!(
+            "the vm ended with {vm_result:?}, the reference with {reference_result:?}"
+        )));
+    }
+    if vm.field().to_string() != interpreter.field().to_string() {
+        return Ok(Some(format!(
+            "the field differs at the end: the vm has {:?}, the reference has {:?}",
+            vm.field().to_string(),
+            interpreter.field().to_string()
+        )));
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interprets() {
+        let source = "red right three purple left red up one blue up red right three purple right";
+        let mut interpreter = Interpreter::with_io(std::io::empty(), Vec::new());
+        let (ast, _) = crate::parse(source).unwrap();
+        assert!(interpreter.run(&ast, &mut ()).unwrap());
+        assert_eq!(interpreter.output(), b"");
+
+        let source = "red left three purple up red down two blue up purple down red down three blue up purple right";
+        let mut interpreter = Interpreter::with_io(std::io::empty(), Vec::new());
+        let (ast, _) = crate::parse(source).unwrap();
+        assert!(interpreter.run(&ast, &mut ()).unwrap());
+        assert_eq!(interpreter.output(), b"5");
+
+        let source = "red left three purple left red up three purple right";
+        let mut interpreter = Interpreter::with_io(std::io::empty(), Vec::new());
+        interpreter.set_limit(20);
+        let (ast, _) = crate::parse(source).unwrap();
+        assert!(!interpreter.run(&ast, &mut ()).unwrap());
+    }
+
+    #[test]
+    fn examples_agree() {
+        for (example, input) in [
+            ("hello_world", ""),
+            ("equality", ""),
+            ("branching", ""),
+            ("truth-machine", "0\n"),
+            ("truth-machine", "1\n"),
+            ("quine", ""),
+            ("test", ""),
+        ] {
+            let source = std::fs::read_to_string(format!("examples/{example}.stn")).unwrap();
+            let divergence = first_divergence(&source, input, 2000).unwrap();
+            assert_eq!(divergence, None, "{example}");
+        }
+
+        // the first purple up is blocked, and so is the purple right ending the second's true
+        // branch
+        for source in [
+            "#field y..gb o.... p.... .r... #end red right three purple up blue up purple right",
+            "#field .oy.. p.... r.... g...b #end red left three purple up red down one blue up \
+             purple down red up one blue up purple right",
+        ] {
+            assert_eq!(
+                first_divergence(source, "", 2000).unwrap(),
+                None,
+                "{source}"
+            );
+        }
+    }
+}
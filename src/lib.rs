@@ -1,10 +1,23 @@
+pub mod analysis;
+pub mod asm;
+pub mod bytecode;
 pub mod command;
+pub mod cst;
+pub mod debugger;
+pub mod diagnostic;
+pub mod expand;
 pub mod field;
+pub mod fmt;
+pub mod interp;
+pub mod lint;
+pub mod record;
+pub mod trace;
 pub mod vm;
 
 use std::{cmp::Ordering, iter::Peekable};
 
 use command::{Command, Dir, EitherNumber, Stone};
+use field::Field;
 use vm::{Opcode, Operation};
 
 pub fn print_command_opcode(ip: usize, command: &Command, opcode: Opcode) {
@@ -15,8 +28,8 @@ pub fn print_command_opcode(ip: usize, command: &Command, opcode: Opcode) {
 #[macro_export]
 macro_rules! red {
     ($num:ident) => {
-        Some(crate::command::EitherNumber::Red(
-            crate::command::RedNumber::$num,
+        Some($crate::command::EitherNumber::Red(
+            $crate::command::RedNumber::$num,
         ))
     };
 }
@@ -24,8 +37,8 @@ macro_rules! red {
 #[macro_export]
 macro_rules! orange {
     ($num:ident) => {
-        Some(crate::command::EitherNumber::Orange(
-            crate::command::OrangeNumber::$num,
+        Some($crate::command::EitherNumber::Orange(
+            $crate::command::OrangeNumber::$num,
         ))
     };
 }
@@ -40,8 +53,13 @@ pub enum Error {
     },
     SyntaxError {
         why: String,
+        span: Span,
     },
     UnexpectedEof,
+    UnterminatedBlock {
+        dir: Dir,
+        span: Span,
+    },
     ExpectedColor {
         got: Token,
     },
@@ -62,12 +80,161 @@ pub enum Error {
         wanted: &'static str,
         got: &'static str,
     },
+    IndexOutOfBounds {
+        index: i64,
+        len: usize,
+        ip: usize,
+        command: Command,
+    },
+    DivisionByZero {
+        ip: usize,
+        command: Command,
+    },
+    IntegerOverflow {
+        ip: usize,
+        command: Command,
+    },
+    NoArrayInProgress {
+        ip: usize,
+        command: Command,
+    },
+    InvalidInput {
+        input: String,
+        ip: usize,
+        command: Command,
+    },
+    InvalidLayout {
+        why: String,
+        span: Option<Span>,
+    },
+    UnterminatedField {
+        span: Span,
+    },
+    InvalidBytecode {
+        why: String,
+        offset: usize,
+    },
+    InvalidAssembly {
+        why: String,
+        span: Span,
+    },
+    CantDesugar {
+        why: String,
+        span: Span,
+    },
     Quine,
+    // a quine ran from bytecode, which doesn't keep the source
+    QuineWithoutSource,
     IoError {
         err: std::io::Error,
     },
 }
 
+impl Error {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::UnexpectedToken { token } => Some(token.span),
+            Error::SyntaxError { span, .. }
+            | Error::UnterminatedBlock { span, .. }
+            | Error::UnterminatedField { span }
+            | Error::InvalidAssembly { span, .. }
+            | Error::CantDesugar { span, .. } => Some(*span),
+            Error::InvalidLayout { span, .. } => *span,
+            Error::ExpectedColor { got }
+            | Error::ExpectedNumber { got }
+            | Error::ExpectedRedNumber { got }
+            | Error::ExpectedOrangeNumber { got }
+            | Error::ExpectedDir { got } => Some(got.span),
+            _ => None,
+        }
+    }
+
+    pub fn is_parse_error(&self) -> bool {
+        matches!(
+            self,
+            Error::UnknownToken { .. }
+                | Error::UnexpectedToken { .. }
+                | Error::SyntaxError { .. }
+                | Error::UnexpectedEof
+                | Error::UnterminatedBlock { .. }
+                | Error::ExpectedColor { .. }
+                | Error::ExpectedNumber { .. }
+                | Error::ExpectedRedNumber { .. }
+                | Error::ExpectedOrangeNumber { .. }
+                | Error::ExpectedDir { .. }
+                | Error::InvalidLayout { .. }
+                | Error::UnterminatedField { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnknownToken { token } => write!(f, "unknown token `{token}`"),
+            Error::UnexpectedToken { token } => write!(f, "unexpected `{}`", token.kind),
+            Error::SyntaxError { why, .. } => write!(f, "{why}"),
+            Error::UnexpectedEof => write!(f, "unexpected end of file"),
+            Error::UnterminatedBlock { dir, .. } => {
+                write!(f, "purple {dir:?} without corresponding purple right")
+            }
+            Error::ExpectedColor { got } => write!(f, "expected a color, got `{}`", got.kind),
+            Error::ExpectedNumber { got } => write!(f, "expected a number, got `{}`", got.kind),
+            Error::ExpectedRedNumber { got } => {
+                write!(f, "expected one, two, or three, got `{}`", got.kind)
+            }
+            Error::ExpectedOrangeNumber { got } => {
+                write!(f, "expected one or two, got `{}`", got.kind)
+            }
+            Error::ExpectedDir { got } => write!(f, "expected a direction, got `{}`", got.kind),
+            Error::StackUnderflow => write!(f, "stack underflow"),
+            Error::TypeMismatch { wanted, got } => write!(f, "expected {wanted}, got {got}"),
+            Error::IndexOutOfBounds {
+                index,
+                len,
+                ip,
+                command,
+            } => write!(
+                f,
+                "index {index} out of bounds for array of length {len} at {ip} `{command}`"
+            ),
+            Error::DivisionByZero { ip, command } => {
+                write!(f, "division by zero at {ip} `{command}`")
+            }
+            Error::IntegerOverflow { ip, command } => {
+                write!(f, "integer overflow at {ip} `{command}`")
+            }
+            Error::NoArrayInProgress { ip, command } => {
+                write!(f, "no array in progress at {ip} `{command}`")
+            }
+            Error::InvalidInput { input, ip, command } => {
+                write!(f, "invalid input {input:?} at {ip} `{command}`")
+            }
+            Error::InvalidLayout { why, .. } => write!(f, "invalid field layout: {why}"),
+            Error::UnterminatedField { .. } => write!(f, "#field without corresponding #end"),
+            Error::InvalidAssembly { why, .. } => write!(f, "{why}"),
+            Error::CantDesugar { why, .. } => write!(f, "{why}"),
+            Error::InvalidBytecode { why, offset } => {
+                write!(f, "invalid bytecode at byte {offset}: {why}")
+            }
+            Error::Quine => write!(f, "quine"),
+            Error::QuineWithoutSource => {
+                write!(f, "quine, but the program was compiled without its source")
+            }
+            Error::IoError { err } => write!(f, "i/o error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IoError { err } => Some(err),
+            _ => None,
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
         Error::IoError { err }
@@ -75,7 +242,40 @@ impl From<std::io::Error> for Error {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub enum Token {
+pub struct Span {
+    pub offset: usize,
+    pub len: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn to(self, end: Span) -> Span {
+        Span {
+            len: end.offset + end.len - self.offset,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn is_color(&self) -> bool {
+        self.kind.is_color()
+    }
+
+    pub fn is_number(&self) -> bool {
+        self.kind.is_number()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TokenKind {
     Red,
     Orange,
     Yellow,
@@ -92,36 +292,66 @@ pub enum Token {
     Eof,
 }
 
-impl Token {
+impl TokenKind {
     pub fn is_color(&self) -> bool {
         matches!(
             self,
-            Token::Red | Token::Orange | Token::Yellow | Token::Green | Token::Blue | Token::Purple
+            TokenKind::Red
+                | TokenKind::Orange
+                | TokenKind::Yellow
+                | TokenKind::Green
+                | TokenKind::Blue
+                | TokenKind::Purple
         )
     }
 
     pub fn is_number(&self) -> bool {
-        matches!(self, Token::One | Token::Two | Token::Three)
+        matches!(self, TokenKind::One | TokenKind::Two | TokenKind::Three)
     }
 }
 
-impl TryFrom<&str> for Token {
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TokenKind::Red => "red",
+                TokenKind::Orange => "orange",
+                TokenKind::Yellow => "yellow",
+                TokenKind::Green => "green",
+                TokenKind::Blue => "blue",
+                TokenKind::Purple => "purple",
+                TokenKind::Up => "up",
+                TokenKind::Down => "down",
+                TokenKind::Left => "left",
+                TokenKind::Right => "right",
+                TokenKind::One => "one",
+                TokenKind::Two => "two",
+                TokenKind::Three => "three",
+                TokenKind::Eof => "end of file",
+            }
+        )
+    }
+}
+
+impl TryFrom<&str> for TokenKind {
     type Error = Error;
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
-            "red" => Ok(Token::Red),
-            "orange" => Ok(Token::Orange),
-            "yellow" => Ok(Token::Yellow),
-            "green" => Ok(Token::Green),
-            "blue" => Ok(Token::Blue),
-            "purple" => Ok(Token::Purple),
-            "up" => Ok(Token::Up),
-            "down" => Ok(Token::Down),
-            "left" => Ok(Token::Left),
-            "right" => Ok(Token::Right),
-            "one" => Ok(Token::One),
-            "two" => Ok(Token::Two),
-            "three" => Ok(Token::Three),
+            "red" => Ok(TokenKind::Red),
+            "orange" => Ok(TokenKind::Orange),
+            "yellow" => Ok(TokenKind::Yellow),
+            "green" => Ok(TokenKind::Green),
+            "blue" => Ok(TokenKind::Blue),
+            "purple" => Ok(TokenKind::Purple),
+            "up" => Ok(TokenKind::Up),
+            "down" => Ok(TokenKind::Down),
+            "left" => Ok(TokenKind::Left),
+            "right" => Ok(TokenKind::Right),
+            "one" => Ok(TokenKind::One),
+            "two" => Ok(TokenKind::Two),
+            "three" => Ok(TokenKind::Three),
             _ => Err(Error::UnknownToken {
                 token: value.to_string(),
             }),
@@ -163,6 +393,14 @@ impl Ast {
     pub fn is_else(&self) -> bool {
         self.command().is_else()
     }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Ast::PurpleUp { begin, end, .. } => begin.span().to(end.span()),
+            Ast::PurpleLeft { begin, end, .. } => begin.span().to(end.span()),
+            Ast::Normal { command, .. } => command.span(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -174,17 +412,14 @@ pub struct Else {
 #[derive(Debug, Clone, Copy)]
 pub struct Number {
     number: EitherNumber,
-    #[allow(dead_code)]
     number_token: Token,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct AstCommand {
     color: Stone,
-    #[allow(dead_code)]
     color_token: Token,
     dir: Dir,
-    #[allow(dead_code)]
     dir_token: Token,
     number: Option<Number>,
 }
@@ -197,53 +432,154 @@ impl AstCommand {
     fn is_else(&self) -> bool {
         self.color == Stone::Purple && self.dir == Dir::Down
     }
+
+    pub fn span(&self) -> Span {
+        let end = match self.number {
+            Some(number) => number.number_token,
+            None => self.dir_token,
+        };
+        self.color_token.span.to(end.span)
+    }
 }
 
 pub fn scan(source: &str) -> impl Iterator<Item = Token> + '_ {
-    source
-        .split_whitespace()
-        .filter_map(|sub| Token::try_from(sub).ok())
+    // the rows of a #field block aren't code, even if one happens to look like a keyword
+    let mut in_field = false;
+    words(source).filter_map(move |(word, span)| {
+        match word {
+            "#field" => in_field = true,
+            "#end" => in_field = false,
+            _ if in_field => {}
+            _ => {
+                return Some(Token {
+                    kind: TokenKind::try_from(word).ok()?,
+                    span,
+                })
+            }
+        }
+        None
+    })
 }
 
-pub fn parse(source: &str) -> Result<Vec<Ast>, Error> {
+// whitespace-separated words along with where they are in the source
+fn words(source: &str) -> impl Iterator<Item = (&str, Span)> + '_ {
+    let mut chars = source.char_indices().peekable();
+    let (mut line, mut column) = (1, 1);
+
+    std::iter::from_fn(move || {
+        while let Some((_, c)) = chars.next_if(|(_, c)| c.is_whitespace()) {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+
+        let (offset, _) = *chars.peek()?;
+        let start_column = column;
+        let mut end = offset;
+        while let Some((i, c)) = chars.next_if(|(_, c)| !c.is_whitespace()) {
+            end = i + c.len_utf8();
+            column += 1;
+        }
+
+        Some((
+            &source[offset..end],
+            Span {
+                offset,
+                len: end - offset,
+                line,
+                column: start_column,
+            },
+        ))
+    })
+}
+
+// the field comes from a #field block, if there is one
+pub fn parse(source: &str) -> Result<(Vec<Ast>, Option<Field>), Error> {
+    let (ast, errors) = parse_recovering(source);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok((ast, parse_field(source)?)),
+    }
+}
+
+// skips ahead to the next color after each error, so the ast may be missing statements
+pub fn parse_recovering(source: &str) -> (Vec<Ast>, Vec<Error>) {
     let mut ast = Vec::new();
+    let mut errors = Vec::new();
+    if let Err(error) = parse_field(source) {
+        errors.push(error);
+    }
+
     let mut tokens = scan(source).peekable();
     let scanner = &mut tokens;
 
     while scanner.peek().is_some() {
-        ast.push(parse_statement(scanner)?);
+        match parse_statement(scanner, &mut errors) {
+            Ok(statement) => ast.push(statement),
+            Err(error) => {
+                errors.push(error);
+                synchronize(scanner);
+            }
+        }
     }
 
-    Ok(ast)
+    (ast, errors)
 }
 
-fn parse_statement<I: Iterator<Item = Token>>(scanner: &mut Peekable<I>) -> Result<Ast, Error> {
-    parse_statement_rec(scanner, false)
+// #field, then one word per row of the field, then #end
+fn parse_field(source: &str) -> Result<Option<Field>, Error> {
+    let mut words = words(source);
+    let Some((_, begin)) = words.find(|(word, _)| *word == "#field") else {
+        return Ok(None);
+    };
+
+    let mut rows = Vec::new();
+    loop {
+        match words.next() {
+            Some(("#end", _)) => break,
+            Some(row) => rows.push(row),
+            None => return Err(Error::UnterminatedField { span: begin }),
+        }
+    }
+
+    if let Some((_, span)) = words.find(|(word, _)| *word == "#field") {
+        return Err(Error::InvalidLayout {
+            why: String::from("more than one #field block"),
+            span: Some(span),
+        });
+    }
+
+    Field::from_rows(rows.into_iter(), Some(begin)).map(Some)
+}
+
+fn synchronize<I: Iterator<Item = Token>>(scanner: &mut Peekable<I>) {
+    while scanner.next_if(|token| !token.is_color()).is_some() {}
+}
+
+fn parse_statement<I: Iterator<Item = Token>>(
+    scanner: &mut Peekable<I>,
+    errors: &mut Vec<Error>,
+) -> Result<Ast, Error> {
+    parse_statement_rec(scanner, errors, false)
 }
 
 fn parse_statement_rec<I: Iterator<Item = Token>>(
     scanner: &mut Peekable<I>,
+    errors: &mut Vec<Error>,
     in_while_or_if: bool,
 ) -> Result<Ast, Error> {
     let command = consume_command(scanner)?;
     match (command.color, command.dir, command.number) {
         (Stone::Purple, Dir::Up, None) => {
-            let mut next = parse_statement_rec(scanner, true)?;
-            let mut body = Vec::new();
-            while !next.is_end() && !next.is_else() {
-                body.push(next);
-                next = parse_statement_rec(scanner, true)?;
-            }
+            let (body, mut next) = parse_block(scanner, errors, command, true)?;
 
             let else_ = if next.is_else() {
-                let else_ = next.command();
-
-                let mut body = Vec::new();
-                next = parse_statement_rec(scanner, true)?;
-                while !next.is_end() {
-                    body.push(next);
-                    next = parse_statement_rec(scanner, true)?;
-                }
+                let else_ = next;
+                let (body, end) = parse_block(scanner, errors, command, false)?;
+                next = end;
 
                 Some(Else { else_, body })
             } else {
@@ -254,28 +590,24 @@ fn parse_statement_rec<I: Iterator<Item = Token>>(
                 begin: command,
                 body,
                 else_,
-                end: next.command(),
+                end: next,
             })
         }
 
         (Stone::Purple, Dir::Left, None) => {
-            let mut next = parse_statement_rec(scanner, true)?;
-            let mut body = Vec::new();
-            while !next.is_end() {
-                body.push(next);
-                next = parse_statement_rec(scanner, true)?;
-            }
+            let (body, end) = parse_block(scanner, errors, command, false)?;
 
             Ok(Ast::PurpleLeft {
                 begin: command,
                 body,
-                end: next.command(),
+                end,
             })
         }
 
         (Stone::Purple, dir @ (Dir::Right | Dir::Down), None) if !in_while_or_if => {
             Err(Error::SyntaxError {
                 why: format!("purple {dir:?} without corresponding purple up or purple left"),
+                span: command.span(),
             })
         }
 
@@ -287,6 +619,43 @@ fn parse_statement_rec<I: Iterator<Item = Token>>(
     }
 }
 
+// returns the body and the purple right (or purple down, if allowed) that ended it
+fn parse_block<I: Iterator<Item = Token>>(
+    scanner: &mut Peekable<I>,
+    errors: &mut Vec<Error>,
+    begin: AstCommand,
+    allow_else: bool,
+) -> Result<(Vec<Ast>, AstCommand), Error> {
+    let mut body = Vec::new();
+
+    loop {
+        if scanner.peek().is_none() {
+            return Err(Error::UnterminatedBlock {
+                dir: begin.dir,
+                span: begin.span(),
+            });
+        }
+
+        match parse_statement_rec(scanner, errors, true) {
+            Ok(next) if next.is_end() || (allow_else && next.is_else()) => {
+                return Ok((body, next.command()))
+            }
+
+            Ok(next) if next.is_else() => errors.push(Error::SyntaxError {
+                why: String::from("purple Down without corresponding purple up"),
+                span: next.span(),
+            }),
+
+            Ok(next) => body.push(next),
+
+            Err(error) => {
+                errors.push(error);
+                synchronize(scanner);
+            }
+        }
+    }
+}
+
 fn parse_number<I: Iterator<Item = Token>>(
     scanner: &mut Peekable<I>,
     color: Stone,
@@ -295,7 +664,7 @@ fn parse_number<I: Iterator<Item = Token>>(
         return Ok(None);
     }
 
-    let number_token = next(scanner)?;
+    let number_token = peek(scanner)?;
     if !number_token.is_number() {
         return Err(Error::ExpectedNumber { got: number_token });
     }
@@ -305,6 +674,7 @@ fn parse_number<I: Iterator<Item = Token>>(
         Stone::Orange => EitherNumber::Orange(number_token.try_into()?),
         _ => unreachable!(),
     };
+    scanner.next();
 
     Ok(Some(Number {
         number,
@@ -312,13 +682,15 @@ fn parse_number<I: Iterator<Item = Token>>(
     }))
 }
 
+// only the color is consumed up front, so a bad direction or number can start the next command
 fn consume_command<I: Iterator<Item = Token>>(
     scanner: &mut Peekable<I>,
 ) -> Result<AstCommand, Error> {
     let color_token = next(scanner)?;
     let color = color_token.try_into()?;
-    let dir_token = next(scanner)?;
+    let dir_token = peek(scanner)?;
     let dir = dir_token.try_into()?;
+    scanner.next();
     let number = parse_number(scanner, color)?;
 
     Ok(AstCommand {
@@ -334,6 +706,10 @@ fn next<I: Iterator<Item = Token>>(scanner: &mut Peekable<I>) -> Result<Token, E
     scanner.next().ok_or(Error::UnexpectedEof)
 }
 
+fn peek<I: Iterator<Item = Token>>(scanner: &mut Peekable<I>) -> Result<Token, Error> {
+    scanner.peek().copied().ok_or(Error::UnexpectedEof)
+}
+
 pub fn compile(ast: &[Ast]) -> Vec<Operation> {
     let mut ops = Vec::new();
 
@@ -342,6 +718,7 @@ pub fn compile(ast: &[Ast]) -> Vec<Operation> {
     }
 
     assert!(ops.iter().all(|op| op.opcode != Opcode::Die));
+    debug_assert_eq!(vm::verify(&ops), Ok(()));
 
     ops
 }
@@ -352,14 +729,20 @@ fn compile_node(ops: &mut Vec<Operation>, node: &Ast) {
             begin, body, end, ..
         } => compile_while(ops, *begin, body, *end),
         Ast::PurpleUp {
-            begin, body, else_, ..
-        } => compile_if(ops, *begin, body, else_.as_ref()),
+            begin,
+            body,
+            else_,
+            end,
+        } => compile_if(ops, *begin, body, else_.as_ref(), *end),
         Ast::Normal { command } => compile_normal(ops, *command),
     }
 }
 
+// every purple command moves its stone when it's reached, so jumps land on them rather
+// than past them. their jumps run even if the stone is blocked. the head of a loop moves
+// each time it checks the condition, and leaving the loop skips its purple right
 fn compile_while(ops: &mut Vec<Operation>, begin: AstCommand, body: &[Ast], end: AstCommand) {
-    let repeat_idx = ops.len();
+    let head_idx = ops.len();
     ops.push(Operation::die());
     for command in body {
         compile_node(ops, command);
@@ -367,17 +750,26 @@ fn compile_while(ops: &mut Vec<Operation>, begin: AstCommand, body: &[Ast], end:
 
     ops.push(Operation {
         command: end.into(),
-        opcode: Opcode::JumpBackward(repeat_idx + 1),
+        opcode: Opcode::JumpBackward(head_idx),
+        span: Some(end.span()),
     });
 
-    ops[repeat_idx] = Operation {
+    ops[head_idx] = Operation {
         command: begin.into(),
         opcode: Opcode::JumpFalse(ops.len()),
+        span: Some(begin.span()),
     };
 }
 
-fn compile_if(ops: &mut Vec<Operation>, begin: AstCommand, body: &[Ast], else_: Option<&Else>) {
-    // JumpFalse forward to else/end
+// purple down moves only when the else branch runs, and purple right moves after either
+// branch. the true branch gets its own copy of purple right that jumps past the else
+fn compile_if(
+    ops: &mut Vec<Operation>,
+    begin: AstCommand,
+    body: &[Ast],
+    else_: Option<&Else>,
+    end: AstCommand,
+) {
     let begin_idx = ops.len();
     ops.push(Operation::die());
 
@@ -385,29 +777,51 @@ fn compile_if(ops: &mut Vec<Operation>, begin: AstCommand, body: &[Ast], else_:
         compile_node(ops, command);
     }
 
-    ops[begin_idx] = Operation {
-        command: begin.into(),
-        opcode: Opcode::JumpFalse(ops.len()),
-    };
-
     if let Some(else_) = else_ {
-        // unconditional JumpForward
-        let else_idx = ops.len();
+        let true_end_idx = ops.len();
         ops.push(Operation::die());
 
+        let else_idx = ops.len();
+        ops.push(Operation {
+            command: else_.else_.into(),
+            opcode: Opcode::JumpForward(else_idx + 1),
+            span: Some(else_.else_.span()),
+        });
         for command in &else_.body {
             compile_node(ops, command);
         }
 
-        ops[else_idx] = Operation {
-            command: else_.else_.into(),
-            opcode: Opcode::JumpForward(ops.len()),
+        ops[true_end_idx] = Operation {
+            command: end.into(),
+            opcode: Opcode::JumpForward(ops.len() + 1),
+            span: Some(end.span()),
+        };
+        ops[begin_idx] = Operation {
+            command: begin.into(),
+            opcode: Opcode::JumpFalse(else_idx),
+            span: Some(begin.span()),
+        };
+    } else {
+        ops[begin_idx] = Operation {
+            command: begin.into(),
+            opcode: Opcode::JumpFalse(ops.len()),
+            span: Some(begin.span()),
         };
     }
+
+    let end_idx = ops.len();
+    ops.push(Operation {
+        command: end.into(),
+        opcode: Opcode::JumpForward(end_idx + 1),
+        span: Some(end.span()),
+    });
 }
 
 fn compile_normal(ops: &mut Vec<Operation>, command: AstCommand) {
-    ops.push(Operation::from(command.into()));
+    ops.push(Operation {
+        span: Some(command.span()),
+        ..Operation::from(command.into())
+    });
 }
 
 #[derive(Clone, Debug)]
@@ -487,6 +901,29 @@ impl TryInto<bool> for Value {
     }
 }
 
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Num(num) => write!(f, "{num}"),
+            Value::Arr(arr) => write!(f, "{arr:?}"),
+            Value::Bool(bool) => write!(f, "{bool}"),
+        }
+    }
+}
+
+// displays a value the way printc outputs it
+pub struct Chars<'a>(&'a Value);
+
+impl std::fmt::Display for Chars<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Value::Num(num) => write!(f, "{}", *num as u8 as char),
+            Value::Arr(arr) => arr.iter().try_for_each(|c| write!(f, "{}", c.as_chars())),
+            Value::Bool(bool) => write!(f, "{bool}"),
+        }
+    }
+}
+
 impl Value {
     pub fn type_name(&self) -> &'static str {
         match self {
@@ -496,30 +933,8 @@ impl Value {
         }
     }
 
-    pub fn print_as_char(&self) {
-        if self.is_num() {
-            print!("{}", self.as_num() as u8 as char);
-        } else if self.is_arr() {
-            for c in self.as_slice() {
-                c.print_as_char();
-            }
-        } else if self.is_bool() {
-            print!("{}", self.as_bool());
-        } else {
-            print!("null");
-        }
-    }
-
-    pub fn print_as_num(&self) {
-        if self.is_num() {
-            print!("{}", self.as_num());
-        } else if self.is_arr() {
-            print!("{:?}", self.as_slice());
-        } else if self.is_bool() {
-            print!("{}", self.as_bool());
-        } else {
-            print!("null");
-        }
+    pub fn as_chars(&self) -> Chars<'_> {
+        Chars(self)
     }
 
     pub fn is_num(&self) -> bool {
@@ -590,3 +1005,103 @@ impl Value {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_spans() {
+        let tokens = scan("ünï red\n  up\tthree").collect::<Vec<_>>();
+        assert_eq!(
+            tokens.iter().map(|token| token.span).collect::<Vec<_>>(),
+            vec![
+                Span {
+                    offset: 6,
+                    len: 3,
+                    line: 1,
+                    column: 5,
+                },
+                Span {
+                    offset: 12,
+                    len: 2,
+                    line: 2,
+                    column: 3,
+                },
+                Span {
+                    offset: 15,
+                    len: 5,
+                    line: 2,
+                    column: 6,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn error_spans() {
+        let Err(Error::ExpectedDir { got }) = parse("red up one\nred blue up") else {
+            panic!("expected ExpectedDir");
+        };
+        assert_eq!(
+            (got.kind, got.span.line, got.span.column),
+            (TokenKind::Blue, 2, 5)
+        );
+
+        let Err(Error::SyntaxError { span, .. }) = parse("red up one\n  purple  right") else {
+            panic!("expected SyntaxError");
+        };
+        assert_eq!((span.line, span.column, span.len), (2, 3, 13));
+    }
+
+    #[test]
+    fn recover() {
+        let (ast, errors) = parse_recovering(
+            "red up\nblue up\norange down three\npurple down\nred up one\npurple left\nblue up\n",
+        );
+        assert_eq!(ast.len(), 2);
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                Error::ExpectedNumber { .. },
+                Error::ExpectedOrangeNumber { .. },
+                Error::SyntaxError { .. },
+                Error::UnterminatedBlock { dir: Dir::Left, .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn field_pragma() {
+        let source = "red up one\n#field\n  b...o\n  .r..g\n  .....\n  ..y.p\n#end\nblue up\n";
+        let (ast, field) = parse(source).unwrap();
+        assert_eq!(ast.len(), 2);
+        let field = field.unwrap();
+        assert_eq!((field.width(), field.height()), (5, 4));
+        assert_eq!(field.position(Stone::Red), Some((1, 1)));
+
+        assert!(parse("red up one").unwrap().1.is_none());
+
+        let Err(Error::InvalidLayout {
+            span: Some(span), ..
+        }) = parse("#field\nb...o\n.r..g\n..y.p.\n#end")
+        else {
+            panic!("expected InvalidLayout");
+        };
+        assert_eq!((span.line, span.column), (4, 1));
+
+        assert!(matches!(
+            parse("#field\nb...o\n.r..g\n..y.p\n"),
+            Err(Error::UnterminatedField { .. })
+        ));
+
+        // a single row would wrap vertical moves back onto the same space
+        let Err(Error::InvalidLayout {
+            span: Some(span), ..
+        }) = parse("#field roygbp #end red up one")
+        else {
+            panic!("expected InvalidLayout");
+        };
+        assert_eq!((span.line, span.column), (1, 1));
+    }
+}
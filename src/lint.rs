@@ -0,0 +1,458 @@
+use crate::{
+    analysis::Analysis,
+    command::{Command, Dir, Stone},
+    field::Field,
+    Ast, AstCommand, Span,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lint {
+    pub message: String,
+    pub span: Span,
+    pub hint: Option<String>,
+}
+
+impl Lint {
+    fn new(message: &str, span: Span, hint: &str) -> Lint {
+        Lint {
+            message: message.to_string(),
+            span,
+            hint: Some(hint.to_string()),
+        }
+    }
+}
+
+// blocks that ran differently before purple moved the same way on every path. see control
+// flow in the README. the warnings about when purple moves are left out if nothing after it
+// depends on where purple is
+pub fn compat(ast: &[Ast], field: &Field) -> Vec<Lint> {
+    let program_end = ast.last().map_or(0, |node| end_of(node.span()));
+    let analysis = Analysis::new(ast, field);
+    let mut commands = Vec::new();
+    flatten(ast, &mut commands);
+    let move_matters = |purple: &AstCommand| move_matters(ast, field, &analysis, &commands, purple);
+
+    let mut lints = Vec::new();
+    compat_rec(ast, program_end, &move_matters, &mut lints);
+    lints.sort_by_key(|lint| lint.span.offset);
+    lints
+}
+
+// whether the program could notice a purple command's move: it pushes another stone, or
+// some command does something different without it
+fn move_matters(
+    ast: &[Ast],
+    field: &Field,
+    analysis: &Analysis,
+    commands: &[AstCommand],
+    purple: &AstCommand,
+) -> bool {
+    let Some(outcomes) = analysis.outcomes(purple) else {
+        return true;
+    };
+    if outcomes.iter().flatten().any(|command| command.side_effect) {
+        return true;
+    }
+
+    let without = Analysis::without_move(ast, field, purple);
+    commands
+        .iter()
+        .filter(|command| command.span() != purple.span())
+        .any(
+            |command| match (analysis.outcomes(command), without.outcomes(command)) {
+                (Some(with), Some(without)) => {
+                    with.len() != without.len() || with.iter().any(|ran| !without.contains(ran))
+                }
+                _ => true,
+            },
+        )
+}
+
+fn flatten(ast: &[Ast], commands: &mut Vec<AstCommand>) {
+    for node in ast {
+        match node {
+            Ast::Normal { command } => commands.push(*command),
+
+            Ast::PurpleUp {
+                begin,
+                body,
+                else_,
+                end,
+            } => {
+                commands.push(*begin);
+                flatten(body, commands);
+                if let Some(else_) = else_ {
+                    commands.push(else_.else_);
+                    flatten(&else_.body, commands);
+                }
+                commands.push(*end);
+            }
+
+            Ast::PurpleLeft { begin, body, end } => {
+                commands.push(*begin);
+                flatten(body, commands);
+                commands.push(*end);
+            }
+        }
+    }
+}
+
+fn end_of(span: Span) -> usize {
+    span.offset + span.len
+}
+
+fn compat_rec(
+    ast: &[Ast],
+    program_end: usize,
+    move_matters: &dyn Fn(&AstCommand) -> bool,
+    lints: &mut Vec<Lint>,
+) {
+    for node in ast {
+        // whether there's a command after it that used to be skipped
+        let followed = end_of(node.span()) < program_end;
+
+        match node {
+            Ast::PurpleUp {
+                begin,
+                body,
+                else_,
+                end,
+            } => {
+                if move_matters(end) {
+                    lints.push(Lint::new(
+                        "purple right now moves at the end of an if",
+                        end.span(),
+                        "it used to be left out, so the purple stone stayed where it was",
+                    ));
+                }
+
+                match else_ {
+                    Some(else_) => {
+                        if move_matters(&else_.else_) {
+                            lints.push(Lint::new(
+                                "purple down now moves before the else branch runs",
+                                else_.else_.span(),
+                                "it used to move after the true branch instead",
+                            ));
+                        }
+                        compat_rec(&else_.body, program_end, move_matters, lints);
+                    }
+                    None if followed => lints.push(Lint::new(
+                        "the command after this if now runs when the condition is false",
+                        begin.span(),
+                        "it used to be skipped",
+                    )),
+                    None => {}
+                }
+
+                compat_rec(body, program_end, move_matters, lints);
+            }
+
+            Ast::PurpleLeft { begin, body, .. } => {
+                lints.push(Lint::new(
+                    "purple left now moves and checks the condition on every pass through the loop",
+                    begin.span(),
+                    "it used to only run the first time, so the loop never ended once started",
+                ));
+                if followed {
+                    lints.push(Lint::new(
+                        "the command after this loop now runs when the loop ends",
+                        begin.span(),
+                        "it used to be skipped",
+                    ));
+                }
+
+                compat_rec(body, program_end, move_matters, lints);
+            }
+
+            Ast::Normal { .. } => {}
+        }
+    }
+}
+
+// commands that are blocked, cut short, or push other stones in every field they could
+// start from. commands the analysis lost track of aren't checked
+pub fn collisions(ast: &[Ast], field: &Field) -> Vec<Lint> {
+    let analysis = Analysis::new(ast, field);
+    let mut commands = Vec::new();
+    flatten(ast, &mut commands);
+    let mut lints = Vec::new();
+    for command in commands {
+        collision(command, &analysis, &mut lints);
+    }
+    lints.sort_by_key(|lint| lint.span.offset);
+    lints
+}
+
+fn collision(command: AstCommand, analysis: &Analysis, lints: &mut Vec<Lint>) {
+    let Some(fields) = analysis
+        .fields_before(&command)
+        .filter(|fields| !fields.is_empty())
+    else {
+        return;
+    };
+    let written = Command::from(command);
+    let span = command.span();
+    // a fix is only suggested when the field is known exactly
+    let known = match fields {
+        [field] => Some(field),
+        _ => None,
+    };
+
+    let ran = fields
+        .iter()
+        .map(|field| field.clone().commands_for(written, &mut ()))
+        .collect::<Vec<_>>();
+    let own = ran
+        .iter()
+        .map(|commands| {
+            commands
+                .iter()
+                .find(|command| !command.side_effect)
+                .copied()
+        })
+        .collect::<Vec<_>>();
+
+    if own.iter().all(Option::is_none) {
+        let blockers = fields
+            .iter()
+            .map(|field| field.next_to(written.color, written.dir))
+            .collect::<Vec<_>>();
+        let hint = match blockers[..] {
+            [blocker, ..] if blockers.iter().all(|&other| other == blocker) => {
+                let mut hint = format!("{blocker} is in the way");
+                if let Some(fix) = known.and_then(|field| fix(field, written, blocker)) {
+                    hint.push_str(&format!(", {fix}"));
+                }
+                hint
+            }
+            _ => String::from("something is in the way every time it runs"),
+        };
+        // purple still jumps when it's blocked, so only the stone stays put
+        let message = if written.color == Stone::Purple {
+            let still = match written.dir {
+                Dir::Up => "its condition is still checked",
+                Dir::Down => "the else still runs when the condition is false",
+                Dir::Left => "the loop still checks its condition",
+                Dir::Right => "it still jumps to the end of its block or the top of its loop",
+            };
+            format!("`{written}` is always blocked, so it never moves, though {still}")
+        } else {
+            format!("`{written}` is always blocked, so it never moves or runs")
+        };
+        lints.push(Lint {
+            message,
+            span,
+            hint: Some(hint),
+        });
+        // a blocked stone doesn't push anything either
+        return;
+    }
+
+    if own
+        .iter()
+        .all(|ran| ran.is_some_and(|ran| ran.magnitude() < written.magnitude()))
+    {
+        let message = match own[..] {
+            [Some(first), ..] if own.iter().all(|&other| other == Some(first)) => {
+                format!("`{written}` is always cut short, so it runs as `{first}`")
+            }
+            _ => format!("`{written}` is always cut short"),
+        };
+        let hint = known.map(|field| {
+            let mut stopped = field.clone();
+            stopped.commands_for(written, &mut ());
+            let blocker = stopped.next_to(written.color, written.dir);
+            match fix(field, written, blocker) {
+                Some(fix) => format!("{blocker} stops it, {fix}"),
+                None => format!("{blocker} stops it"),
+            }
+        });
+        lints.push(Lint {
+            message,
+            span,
+            hint,
+        });
+    }
+
+    let pushed = ran
+        .iter()
+        .map(|commands| {
+            commands
+                .iter()
+                .filter(|command| command.side_effect)
+                .copied()
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    if let [first, ..] = &pushed[..] {
+        if !first.is_empty() && pushed.iter().all(|other| other == first) {
+            // the stone it runs into is pushed last, after anything that stone pushed
+            let hit = first[first.len() - 1].color;
+            let mut stones = Vec::new();
+            for command in first.iter().rev() {
+                if !stones.contains(&command.color) {
+                    stones.push(command.color);
+                }
+            }
+            let stones = stones
+                .iter()
+                .map(|stone| stone.to_string())
+                .collect::<Vec<_>>()
+                .join(" and ");
+            let commands = ran[0]
+                .iter()
+                .map(|command| format!("`{command}`"))
+                .collect::<Vec<_>>()
+                .join(" then ");
+            lints.push(Lint {
+                message: format!("`{written}` pushes {stones}, so it runs {commands}"),
+                span,
+                hint: known.and_then(|field| fix(field, written, hit)),
+            });
+        }
+    }
+}
+
+// a move just before the command that gets a stone out of its way, so it runs as written.
+// it's only a suggestion: the move runs a command of its own
+fn fix(field: &Field, written: Command, stone: Stone) -> Option<String> {
+    let sideways = match written.dir {
+        Dir::Up | Dir::Down => [Dir::Left, Dir::Right],
+        Dir::Left | Dir::Right => [Dir::Up, Dir::Down],
+    };
+    // moving the stone in the way, or else the command's own stone onto a clear path.
+    // purple can't move without changing control flow
+    let candidates = [stone, written.color]
+        .into_iter()
+        .filter(|&color| color != Stone::Purple && color != Stone::__)
+        .flat_map(|color| {
+            sideways.map(|dir| Command {
+                color,
+                dir,
+                number: color.number_one(),
+                side_effect: false,
+            })
+        });
+
+    for candidate in candidates {
+        let mut moved = field.clone();
+        if moved.commands_for(candidate, &mut ()) != [candidate] {
+            continue;
+        }
+        if moved.commands_for(written, &mut ()) == [written] {
+            return Some(if candidate.color == written.color {
+                format!(
+                    "`{candidate}` before it would give {} a clear path, though that runs too",
+                    candidate.color
+                )
+            } else {
+                format!(
+                    "`{candidate}` before it would move {} out of the way, though that runs too",
+                    candidate.color
+                )
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compat_lints() {
+        let messages = |source: &str, field: &Field| {
+            let (ast, _) = crate::parse(source).unwrap();
+            compat(&ast, field)
+                .into_iter()
+                .map(|lint| (lint.span.line, lint.message))
+                .collect::<Vec<_>>()
+        };
+
+        // nothing runs into purple wherever it is, so only the control flow changes
+        let source = "purple up blue up purple right\n\
+                      purple left blue up purple right\n\
+                      purple up purple down purple right";
+        assert_eq!(
+            messages(source, &Field::new()),
+            [
+                (
+                    1,
+                    String::from("the command after this if now runs when the condition is false")
+                ),
+                (
+                    2,
+                    String::from(
+                        "purple left now moves and checks the condition on every pass through the loop"
+                    )
+                ),
+                (
+                    2,
+                    String::from("the command after this loop now runs when the loop ends")
+                ),
+            ]
+        );
+
+        // red runs into purple, and how far it gets depends on both purple moves
+        let field = Field::parse("g.o.y\n.....\n.r...\n...p.\nb....").unwrap();
+        assert_eq!(
+            messages("purple up purple down purple right red right three", &field),
+            [
+                (
+                    1,
+                    String::from("purple down now moves before the else branch runs")
+                ),
+                (
+                    1,
+                    String::from("purple right now moves at the end of an if")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn collision_lints() {
+        let source = "red right two red down one\n\
+                      red down one\n\
+                      yellow up\n\
+                      red right two red right three";
+        let (ast, _) = crate::parse(source).unwrap();
+        let lints = collisions(&ast, &Field::new());
+        assert_eq!(
+            lints
+                .iter()
+                .map(|lint| (lint.span.line, lint.message.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (2, "`red down one` is always blocked, so it never moves or runs"),
+                (
+                    3,
+                    "`yellow up` pushes red, so it runs `red up one (side effect)` then `yellow up`"
+                ),
+                (4, "`red right three` is always cut short, so it runs as `red right one`"),
+            ]
+        );
+        assert_eq!(
+            lints[0].hint.as_deref(),
+            Some("yellow is in the way, `yellow left` before it would move yellow out of the way, though that runs too")
+        );
+
+        // purple can't move up, since its column is full, but the if still runs
+        let (ast, _) = crate::parse("red left three purple up red up one purple right").unwrap();
+        let field = Field::parse("p.g.....\no...r...\ny.b.....").unwrap();
+        assert_eq!(
+            collisions(&ast, &field)
+                .iter()
+                .map(|lint| lint.message.as_str())
+                .collect::<Vec<_>>(),
+            ["`purple up` is always blocked, so it never moves, though its condition is still checked"]
+        );
+
+        // red only moves in one branch, so whether blue pushes it isn't known
+        let (ast, _) =
+            crate::parse("red right two purple up red down one purple right yellow up").unwrap();
+        assert_eq!(collisions(&ast, &Field::new()), []);
+    }
+}
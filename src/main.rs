@@ -1,4 +1,23 @@
+mod tui;
+
+use std::{
+    fs::File,
+    io::{BufWriter, IsTerminal, Write},
+};
+
 use gumdrop::Options;
+use rustyline::{error::ReadlineError, Editor};
+
+use stones::{
+    analysis::Analysis,
+    command::{Command, Stone},
+    debugger::{Breakpoint, Debugger, Stop},
+    expand::Expanded,
+    field::Field,
+    record::HtmlRecorder,
+    trace::{JsonTracer, PrintTracer, Tracer},
+    vm::{Operation, Vm},
+};
 
 #[derive(Debug, Options)]
 struct Args {
@@ -52,6 +71,34 @@ struct Args {
         short = "i"
     )]
     interactive: bool,
+
+    #[options(
+        help = "Write a JSON object for every executed command to FILE, one per line.",
+        meta = "FILE",
+        no_short
+    )]
+    trace_json: Option<String>,
+
+    #[options(
+        help = "Write a page to FILE that replays the run frame by frame.",
+        meta = "FILE",
+        no_short
+    )]
+    record_html: Option<String>,
+
+    #[options(
+        help = "Write the compiled program to FILE instead of running it. Files starting with the bytecode header run directly.",
+        meta = "FILE",
+        no_short
+    )]
+    emit_bytecode: Option<String>,
+
+    #[options(
+        help = "Start with the field layout in FILE instead of the default.",
+        meta = "FILE",
+        no_short
+    )]
+    field_file: Option<String>,
 }
 
 impl Args {
@@ -65,49 +112,919 @@ impl Args {
     }
 }
 
+#[derive(Debug, Options)]
+struct DebugArgs {
+    #[options(help = "Print this message.", short = "h")]
+    help: bool,
+
+    #[options(free, help = "File to debug.")]
+    filename: Option<String>,
+
+    #[options(
+        help = "Start with the field layout in FILE instead of the default.",
+        meta = "FILE",
+        no_short
+    )]
+    field_file: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct TuiArgs {
+    #[options(help = "Print this message.", short = "h")]
+    help: bool,
+
+    #[options(free, help = "File to watch.")]
+    filename: Option<String>,
+
+    #[options(
+        help = "Start with the field layout in FILE instead of the default.",
+        meta = "FILE",
+        no_short
+    )]
+    field_file: Option<String>,
+
+    #[options(help = "Read the program's input from FILE.", meta = "FILE")]
+    input: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct AsmArgs {
+    #[options(help = "Print this message.", short = "h")]
+    help: bool,
+
+    #[options(free, help = "Assembly file to run.")]
+    filename: Option<String>,
+
+    #[options(help = "Write bytecode to FILE instead of running it.", meta = "FILE")]
+    output: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct DisasmArgs {
+    #[options(help = "Print this message.", short = "h")]
+    help: bool,
+
+    #[options(free, help = "Source or bytecode file to disassemble.")]
+    filename: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct FmtArgs {
+    #[options(help = "Print this message.", short = "h")]
+    help: bool,
+
+    #[options(
+        free,
+        help = "Files to format in place. If none are given, formats stdin."
+    )]
+    filenames: Vec<String>,
+
+    #[options(
+        help = "Don't write anything, exit with 1 if a file isn't formatted.",
+        no_short
+    )]
+    check: bool,
+}
+
+#[derive(Debug, Options)]
+struct LintArgs {
+    #[options(help = "Print this message.", short = "h")]
+    help: bool,
+
+    #[options(free, help = "File to check.")]
+    filename: Option<String>,
+
+    #[options(
+        help = "Warn about blocks that behaved differently before purple moved the same way on every path.",
+        no_short
+    )]
+    compat: bool,
+
+    #[options(
+        help = "Start with the field layout in FILE instead of the default.",
+        meta = "FILE",
+        no_short
+    )]
+    field_file: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct ExpandArgs {
+    #[options(help = "Print this message.", short = "h")]
+    help: bool,
+
+    #[options(free, help = "File to expand.")]
+    filename: Option<String>,
+
+    #[options(
+        help = "Print the program with every push written out as its own command instead.",
+        no_short
+    )]
+    desugar: bool,
+
+    #[options(
+        help = "Start with the field layout in FILE instead of the default.",
+        meta = "FILE",
+        no_short
+    )]
+    field_file: Option<String>,
+
+    #[options(
+        help = "Run at most N steps to find out what commands with an unknown field do.",
+        meta = "N",
+        default = "100000",
+        no_short
+    )]
+    limit: usize,
+}
+
+const COMMANDS: &str = "  debug   Step through a program with breakpoints and watchpoints.
+  tui     Watch a program run, with the source, field, stack, and output side by side.
+  asm     Run or compile a program written in bytecode assembly.
+  disasm  Print the bytecode of a program as assembly.
+  fmt     Indent blocks and put one command on each line.
+  lint    Warn about code that probably doesn't do what it looks like.
+  expand  Show what each command runs as, including the stones it pushes.";
+
 fn main() {
-    let mut args: Args = Args::parse_args_default_or_exit();
+    let argv = std::env::args().skip(1).collect::<Vec<_>>();
+    match argv.first().map(String::as_str) {
+        Some("debug") => debug(parse_args(Some("debug"), &argv[1..])),
+        Some("tui") => tui(parse_args(Some("tui"), &argv[1..])),
+        Some("asm") => asm(parse_args(Some("asm"), &argv[1..])),
+        Some("disasm") => disasm(parse_args(Some("disasm"), &argv[1..])),
+        Some("fmt") => fmt(parse_args(Some("fmt"), &argv[1..])),
+        Some("lint") => lint(parse_args(Some("lint"), &argv[1..])),
+        Some("expand") => expand(parse_args(Some("expand"), &argv[1..])),
+        _ => run_file(parse_args(None, &argv)),
+    }
+}
+
+// parse_args_default_or_exit, but for subcommands too
+fn parse_args<T: Options>(command: Option<&str>, argv: &[String]) -> T {
+    let program = std::env::args()
+        .next()
+        .unwrap_or_else(|| String::from("stones"));
+    let name = match command {
+        Some(command) => format!("{program} {command}"),
+        None => program,
+    };
+
+    let args = T::parse_args_default(argv).unwrap_or_else(|err| {
+        eprintln!("{name}: {err}");
+        std::process::exit(2);
+    });
+
+    if args.help_requested() {
+        eprintln!("Usage: {name} [OPTIONS]");
+        eprintln!();
+        eprintln!("{}", T::usage());
+        if command.is_none() {
+            eprintln!();
+            eprintln!("Available commands:");
+            eprintln!("{COMMANDS}");
+        }
+        std::process::exit(0);
+    }
+
+    args
+}
+
+fn read_source(filename: &str) -> String {
+    match std::fs::read_to_string(filename) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: could not read {filename}: {err}");
+            std::process::exit(EXIT_IO);
+        }
+    }
+}
+
+fn read_field(path: &str) -> Field {
+    let layout = read_source(path);
+    Field::parse(&layout).unwrap_or_else(|err| fail(&err, path, &layout, EXIT_PARSE))
+}
+
+fn run_file(mut args: Args) {
     args.print_operation = args.print_operation || args.print_field || args.print_stack;
 
+    let mut vm = Vm::new(Vec::new());
+    if let Some(path) = args.field_file.as_ref() {
+        vm.set_field(read_field(path));
+    }
+
+    // the html recorder starts once the source and field are known
+    let mut html_file = args.record_html.as_deref().map(create_file);
+    let mut tracer = (
+        PrintTracer {
+            print_op: args.print_operation,
+            print_field: args.print_field,
+            print_stack: args.print_stack,
+            color: std::io::stdout().is_terminal(),
+        },
+        (
+            args.trace_json
+                .as_deref()
+                .map(create_file)
+                .map(JsonTracer::new),
+            None,
+        ),
+    );
+
+    if let Some(filename) = args.filename.as_ref() {
+        let bytes = match std::fs::read(filename) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                eprintln!("error: could not read {filename}: {err}");
+                std::process::exit(EXIT_IO);
+            }
+        };
+
+        // compiled programs don't have their source
+        let compiled = bytes.starts_with(stones::bytecode::MAGIC);
+        let (source, program, field) = if compiled {
+            let (program, field) = match stones::bytecode::read(bytes.as_slice()) {
+                Ok(read) => read,
+                Err(err) => fail(&err, filename, "", EXIT_PARSE),
+            };
+            if args.print_compiled {
+                print_bytecode(&program);
+            }
+            (String::new(), program, field)
+        } else {
+            let source = match String::from_utf8(bytes) {
+                Ok(source) => source,
+                Err(err) => {
+                    eprintln!("error: could not read {filename}: {err}");
+                    std::process::exit(EXIT_IO);
+                }
+            };
+
+            if args.verify_syntax {
+                let (_, errors) = stones::parse_recovering(&source);
+                for err in &errors {
+                    eprint!("{}", stones::diagnostic::render(err, filename, &source));
+                }
+                if !errors.is_empty() {
+                    std::process::exit(EXIT_PARSE);
+                }
+            }
+
+            let (program, field) = match compile(&args, &source) {
+                Ok(compiled) => compiled,
+                Err(err) => fail(&err, filename, &source, EXIT_PARSE),
+            };
+            (source, program, field)
+        };
+
+        if let Some(path) = args.emit_bytecode.as_ref() {
+            let written = stones::bytecode::write(&program, field.as_ref(), create_file(path));
+            if let Err(err) = written {
+                fail(&err, path, "", EXIT_IO);
+            }
+        }
+
+        if (args.verify_syntax || args.emit_bytecode.is_some()) && !args.interactive {
+            return;
+        }
+
+        vm.load(program);
+        if let Some(field) = field.filter(|_| args.field_file.is_none()) {
+            vm.set_field(field);
+        }
+
+        // stones don't need to move where the field is known ahead of time, unless someone
+        // is watching them
+        let watched = args.print_operation || args.print_field;
+        if !watched && args.trace_json.is_none() && args.record_html.is_none() {
+            if let Ok((ast, _)) = stones::parse(&source) {
+                let analysis = Analysis::new(&ast, vm.field());
+                vm.set_resolved(analysis.resolve_program(vm.program()));
+            }
+        }
+
+        if args.print_any() {
+            println!("program run:");
+        }
+
+        if args.print_field {
+            println!("init\n{}", show_field(vm.field()));
+        }
+
+        tracer.1 .1 = html_file
+            .take()
+            .map(|file| HtmlRecorder::new(file, filename, &source, vm.field()));
+
+        let source_text = (!compiled).then_some(source.as_str());
+        if let Err(err) = run(&mut vm, source_text, &mut tracer) {
+            finish_trace(&args, tracer.1);
+            fail(&err, filename, &source, EXIT_RUNTIME);
+        }
+    }
+
     if args.filename.is_none() || args.interactive {
-        todo!("repl not supported yet");
+        if let Some(file) = html_file {
+            tracer.1 .1 = Some(HtmlRecorder::new(file, "<repl>", "", vm.field()));
+        }
+        repl(&args, &mut vm, &mut tracer);
+    }
+
+    finish_trace(&args, tracer.1);
+}
+
+fn create_file(path: &str) -> BufWriter<File> {
+    match File::create(path) {
+        Ok(file) => BufWriter::new(file),
+        Err(err) => {
+            eprintln!("error: couldn't create {path}: {err}");
+            std::process::exit(EXIT_IO);
+        }
+    }
+}
+
+type Recorders = (
+    Option<JsonTracer<BufWriter<File>>>,
+    Option<HtmlRecorder<BufWriter<File>>>,
+);
+
+fn finish_trace(args: &Args, (json, html): Recorders) {
+    let results = [
+        (json.map(|json| json.finish().map(drop)), &args.trace_json),
+        (html.map(|html| html.finish().map(drop)), &args.record_html),
+    ];
+    for (result, path) in results {
+        if let (Some(Err(err)), Some(path)) = (result, path) {
+            eprintln!("error: couldn't write {path}: {err}");
+            std::process::exit(EXIT_IO);
+        }
+    }
+}
+
+// 2 is taken by gumdrop for bad arguments
+const EXIT_PARSE: i32 = 3;
+const EXIT_RUNTIME: i32 = 4;
+const EXIT_IO: i32 = 5;
+
+fn fail(err: &stones::Error, filename: &str, source: &str, code: i32) -> ! {
+    let _ = std::io::stdout().flush();
+    eprint!("{}", stones::diagnostic::render(err, filename, source));
+    if matches!(err, stones::Error::IoError { .. }) {
+        std::process::exit(EXIT_IO);
     }
+    std::process::exit(code);
+}
 
-    let source = std::fs::read_to_string(args.filename.as_ref().unwrap()).unwrap();
-    let ast = stones::parse(&source).unwrap();
+fn compile(args: &Args, source: &str) -> Result<(Vec<Operation>, Option<Field>), stones::Error> {
+    let (ast, field) = stones::parse(source)?;
     let program = stones::compile(&ast);
 
     if args.print_tokens {
-        println!("tokens:\n{:#?}", stones::scan(&source).collect::<Vec<_>>());
+        println!("tokens:\n{:#?}", stones::scan(source).collect::<Vec<_>>());
     }
     if args.print_ast {
         println!("ast:\n{ast:#?}");
     }
     if args.print_compiled {
-        println!("bytecode:");
-        for (i, stones::vm::Operation { command, opcode }) in program.iter().enumerate() {
-            stones::print_command_opcode(i, command, *opcode);
+        print_bytecode(&program);
+    }
+
+    Ok((program, field))
+}
+
+fn print_bytecode(program: &[Operation]) {
+    println!("bytecode:");
+    for (
+        i,
+        Operation {
+            command, opcode, ..
+        },
+    ) in program.iter().enumerate()
+    {
+        stones::print_command_opcode(i, command, *opcode);
+    }
+}
+
+fn show_field(field: &Field) -> String {
+    if std::io::stdout().is_terminal() {
+        field.ansi().to_string()
+    } else {
+        field.to_string()
+    }
+}
+
+// quines print the source, so running bytecode without one fails at the first quine
+fn run(vm: &mut Vm, source: Option<&str>, tracer: &mut dyn Tracer) -> Result<(), stones::Error> {
+    let mut result = vm.run(tracer);
+    while matches!(result, Err(stones::Error::Quine)) {
+        print!("{}", source.ok_or(stones::Error::QuineWithoutSource)?);
+        result = vm.run(tracer);
+    }
+    result
+}
+
+fn repl(args: &Args, vm: &mut Vm, tracer: &mut dyn Tracer) {
+    let mut editor = Editor::<()>::new();
+
+    if args.print_field {
+        println!("init\n{}", show_field(vm.field()));
+    }
+
+    // lines are collected until they parse, so blocks can span multiple lines
+    let mut source = String::new();
+    loop {
+        let prompt = if source.is_empty() {
+            "stones> "
+        } else {
+            "   ...> "
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                source.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                println!("{err}");
+                break;
+            }
+        };
+
+        editor.add_history_entry(line.as_str());
+        source.push_str(&line);
+        source.push('\n');
+
+        let (program, field) = match compile(args, &source) {
+            Ok(compiled) => compiled,
+            Err(
+                stones::Error::UnexpectedEof
+                | stones::Error::UnterminatedBlock { .. }
+                | stones::Error::UnterminatedField { .. },
+            ) => continue,
+            Err(err) => {
+                eprint!("{}", stones::diagnostic::render(&err, "<repl>", &source));
+                source.clear();
+                continue;
+            }
+        };
+
+        vm.load(program);
+        if let Some(field) = field {
+            vm.set_field(field);
+        }
+        let result = run(vm, Some(&source), tracer);
+        let _ = std::io::stdout().flush();
+        if let Err(err) = result {
+            eprint!("{}", stones::diagnostic::render(&err, "<repl>", &source));
         }
+
+        source.clear();
+    }
+}
+
+const DEBUG_HELP: &str = "\
+step, s                  run the next operation
+next, n                  run the next statement, stepping over whole blocks
+continue, c              run until a breakpoint or the end of the program
+break LINE               stop before running a source line
+break @INDEX             stop before running a bytecode index
+watch STONE row|col N    stop when a stone moves onto a row or column (from 0)
+watch blocked            stop after a command is blocked
+watch partial            stop after a command is partially blocked
+watch pushed             stop after a command pushes other stones
+info                     list breakpoints and watchpoints
+delete N                 delete a breakpoint or watchpoint
+stack, field, array      show the stack, the field, or the array in progress
+list                     show the source around the next operation
+quit, q                  stop debugging
+An empty line repeats the last command.";
+
+fn debug(args: DebugArgs) {
+    let Some(filename) = args.filename.as_ref() else {
+        eprintln!("stones debug: missing filename");
+        std::process::exit(2);
+    };
+
+    let source = read_source(filename);
+    let (ast, field) = match stones::parse(&source) {
+        Ok(parsed) => parsed,
+        Err(err) => fail(&err, filename, &source, EXIT_PARSE),
+    };
+    let program = stones::compile(&ast);
+    let mut vm = Vm::new(program);
+    match (args.field_file.as_ref(), field) {
+        (Some(path), _) => vm.set_field(read_field(path)),
+        (None, Some(field)) => vm.set_field(field),
+        (None, None) => {}
     }
+    let mut debugger = Debugger::new(vm, ast);
+
+    println!("debugging {filename}, type help for a list of commands");
+    show_location(&debugger, &source);
+
+    let mut editor = Editor::<()>::new();
+    let mut last = String::new();
+    loop {
+        let line = match editor.readline("(debug) ") {
+            Ok(line) if line.trim().is_empty() => last.clone(),
+            Ok(line) => {
+                editor.add_history_entry(line.as_str());
+                line
+            }
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                println!("{err}");
+                break;
+            }
+        };
+        last = line.clone();
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let stop = match words.as_slice() {
+            ["step" | "s"] => debugger.step(),
+            ["next" | "n"] => debugger.step_over(),
+            ["continue" | "c"] => debugger.continue_(),
+
+            ["break" | "b", at] => {
+                let breakpoint = match at.strip_prefix('@') {
+                    Some(idx) => idx.parse().map(Breakpoint::Index),
+                    None => at.parse().map(Breakpoint::Line),
+                };
+                match breakpoint {
+                    Ok(breakpoint) => add_breakpoint(&mut debugger, breakpoint),
+                    Err(_) => println!("expected a line number or @index"),
+                }
+                continue;
+            }
+
+            ["watch" | "w", what @ ..] => {
+                let breakpoint = match what {
+                    ["blocked"] => Some(Breakpoint::Blocked),
+                    ["partial"] => Some(Breakpoint::PartiallyBlocked),
+                    ["pushed"] => Some(Breakpoint::Pushed),
+                    [stone, "row", n] => parse_stone(stone)
+                        .zip(n.parse().ok())
+                        .map(|(stone, n)| Breakpoint::Row(stone, n)),
+                    [stone, "col" | "column", n] => parse_stone(stone)
+                        .zip(n.parse().ok())
+                        .map(|(stone, n)| Breakpoint::Column(stone, n)),
+                    _ => None,
+                };
+                match breakpoint {
+                    Some(breakpoint) => add_breakpoint(&mut debugger, breakpoint),
+                    None => println!("expected blocked, partial, pushed, or STONE row|col N"),
+                }
+                continue;
+            }
+
+            ["info" | "i"] => {
+                for (i, breakpoint) in debugger.breakpoints().iter().enumerate() {
+                    println!("{}: {breakpoint}", i + 1);
+                }
+                continue;
+            }
+
+            ["delete" | "d", n] => {
+                match n.parse::<usize>().ok().and_then(|n| n.checked_sub(1)) {
+                    Some(n) if debugger.delete_breakpoint(n).is_some() => {}
+                    _ => println!("no breakpoint {n}"),
+                }
+                continue;
+            }
 
-    if args.filename.is_some() && args.verify_syntax {
+            ["stack"] => {
+                for value in debugger.vm().stack() {
+                    println!("{value:?}");
+                }
+                continue;
+            }
+
+            ["field"] => {
+                print!("{}", show_field(debugger.vm().field()));
+                continue;
+            }
+
+            ["array"] => {
+                match debugger.vm().array_in_progress() {
+                    Some(array) => println!("{array:?}"),
+                    None => println!("no array in progress"),
+                }
+                continue;
+            }
+
+            ["list" | "l"] => {
+                let current = debugger.span().map(|span| span.line);
+                let around = current.unwrap_or(1);
+                for (i, text) in source.lines().enumerate() {
+                    let line = i + 1;
+                    if line + 5 >= around && line <= around + 5 {
+                        let marker = if Some(line) == current { '>' } else { ' ' };
+                        println!("{marker}{line:4} | {text}");
+                    }
+                }
+                continue;
+            }
+
+            ["help" | "h"] => {
+                println!("{DEBUG_HELP}");
+                continue;
+            }
+
+            ["quit" | "q"] => break,
+
+            _ => {
+                println!("unknown command, type help for a list of commands");
+                continue;
+            }
+        };
+
+        match stop {
+            Ok(Stop::Stepped) => show_location(&debugger, &source),
+            Ok(Stop::Breakpoint(i)) => {
+                println!("stopped: {}", debugger.breakpoints()[i]);
+                show_location(&debugger, &source);
+            }
+            Ok(Stop::Halted) => show_location(&debugger, &source),
+            Err(stones::Error::Quine) => {
+                print!("{source}");
+                show_location(&debugger, &source);
+            }
+            Err(err) => eprint!("{}", stones::diagnostic::render(&err, filename, &source)),
+        }
+    }
+}
+
+fn add_breakpoint(debugger: &mut Debugger, breakpoint: Breakpoint) {
+    let n = debugger.add_breakpoint(breakpoint) + 1;
+    println!("{n}: {breakpoint}");
+}
+
+fn parse_stone(word: &str) -> Option<Stone> {
+    stones::scan(word).next()?.try_into().ok()
+}
+
+fn show_location(debugger: &Debugger, source: &str) {
+    let vm = debugger.vm();
+    let Some(operation) = vm.program().get(vm.ip()) else {
+        println!("program finished");
         return;
+    };
+
+    println!("{:4} {}", vm.ip(), operation.command);
+    if let Some(span) = operation.span {
+        let text = source.lines().nth(span.line - 1).unwrap_or("");
+        println!("{:4} | {text}", span.line);
     }
+}
+
+fn tui(args: TuiArgs) {
+    let Some(filename) = args.filename.as_ref() else {
+        eprintln!("stones tui: missing filename");
+        std::process::exit(2);
+    };
 
-    let mut vm = stones::vm::Vm::new(program);
+    let source = read_source(filename);
+    let (ast, field) = match stones::parse(&source) {
+        Ok(parsed) => parsed,
+        Err(err) => fail(&err, filename, &source, EXIT_PARSE),
+    };
 
-    if args.print_any() {
-        println!("program run:");
+    // stdin belongs to the controls, so input comes from a file
+    let input: Box<dyn std::io::Read> = match args.input.as_ref() {
+        Some(path) => match std::fs::File::open(path) {
+            Ok(file) => Box::new(std::io::BufReader::new(file)),
+            Err(err) => {
+                eprintln!("error: could not read {path}: {err}");
+                std::process::exit(EXIT_IO);
+            }
+        },
+        None => Box::new(std::io::empty()),
+    };
+
+    let mut vm = Vm::with_io(stones::compile(&ast), input, Vec::new());
+    match (args.field_file.as_ref(), field) {
+        (Some(path), _) => vm.set_field(read_field(path)),
+        (None, Some(field)) => vm.set_field(field),
+        (None, None) => {}
     }
 
-    if args.print_field {
-        println!("init\n{:?}\n", vm.field());
+    if let Err(err) = tui::Tui::new(vm, source, filename.clone()).run() {
+        eprintln!("error: {err}");
+        std::process::exit(EXIT_IO);
     }
+}
 
-    let mut result = vm.run(args.print_operation, args.print_field, args.print_stack);
-    while matches!(result, Err(stones::Error::Quine)) {
-        print!("{source}");
-        result = vm.run(args.print_operation, args.print_field, args.print_stack);
+fn asm(args: AsmArgs) {
+    let Some(filename) = args.filename.as_ref() else {
+        eprintln!("stones asm: missing filename");
+        std::process::exit(2);
+    };
+
+    let source = read_source(filename);
+    let (program, field) = match stones::asm::assemble(&source) {
+        Ok(assembled) => assembled,
+        Err(err) => fail(&err, filename, &source, EXIT_PARSE),
+    };
+
+    if let Some(path) = args.output.as_ref() {
+        if let Err(err) = stones::bytecode::write(&program, field.as_ref(), create_file(path)) {
+            fail(&err, path, "", EXIT_IO);
+        }
+        return;
+    }
+
+    let mut vm = Vm::new(program);
+    if let Some(field) = field {
+        vm.set_field(field);
+    }
+    // there's no stones source to print for a quine
+    if let Err(err) = run(&mut vm, None, &mut ()) {
+        fail(&err, filename, &source, EXIT_RUNTIME);
+    }
+}
+
+fn disasm(args: DisasmArgs) {
+    let Some(filename) = args.filename.as_ref() else {
+        eprintln!("stones disasm: missing filename");
+        std::process::exit(2);
+    };
+
+    let bytes = match std::fs::read(filename) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("error: could not read {filename}: {err}");
+            std::process::exit(EXIT_IO);
+        }
+    };
+
+    let (program, field) = if bytes.starts_with(stones::bytecode::MAGIC) {
+        match stones::bytecode::read(bytes.as_slice()) {
+            Ok(read) => read,
+            Err(err) => fail(&err, filename, "", EXIT_PARSE),
+        }
+    } else {
+        let source = String::from_utf8_lossy(&bytes);
+        match stones::parse(&source) {
+            Ok((ast, field)) => (stones::compile(&ast), field),
+            Err(err) => fail(&err, filename, &source, EXIT_PARSE),
+        }
+    };
+
+    print!("{}", stones::asm::disassemble(&program, field.as_ref()));
+}
+
+fn fmt(args: FmtArgs) {
+    if args.filenames.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = std::io::Read::read_to_string(&mut std::io::stdin(), &mut source) {
+            eprintln!("error: could not read stdin: {err}");
+            std::process::exit(EXIT_IO);
+        }
+        match stones::fmt::format(&source) {
+            Ok(formatted) if args.check && formatted != source => std::process::exit(1),
+            Ok(_) if args.check => {}
+            Ok(formatted) => print!("{formatted}"),
+            Err(err) => fail(&err, "<stdin>", &source, EXIT_PARSE),
+        }
+        return;
+    }
+
+    let mut unformatted = false;
+    for filename in &args.filenames {
+        let source = read_source(filename);
+        let formatted = match stones::fmt::format(&source) {
+            Ok(formatted) => formatted,
+            Err(err) => fail(&err, filename, &source, EXIT_PARSE),
+        };
+        if formatted == source {
+            continue;
+        }
+
+        if args.check {
+            println!("{filename}");
+            unformatted = true;
+        } else if let Err(err) = std::fs::write(filename, formatted) {
+            eprintln!("error: couldn't write {filename}: {err}");
+            std::process::exit(EXIT_IO);
+        }
+    }
+
+    if unformatted {
+        std::process::exit(1);
+    }
+}
+
+fn lint(args: LintArgs) {
+    let Some(filename) = args.filename.as_ref() else {
+        eprintln!("stones lint: missing filename");
+        std::process::exit(2);
+    };
+
+    let source = read_source(filename);
+    let (ast, field) = match stones::parse(&source) {
+        Ok(parsed) => parsed,
+        Err(err) => fail(&err, filename, &source, EXIT_PARSE),
+    };
+    let field = match (args.field_file.as_ref(), field) {
+        (Some(path), _) => read_field(path),
+        (None, Some(field)) => field,
+        (None, None) => Field::new(),
+    };
+
+    let mut lints = stones::lint::collisions(&ast, &field);
+    if args.compat {
+        lints.extend(stones::lint::compat(&ast, &field));
+        lints.sort_by_key(|lint| lint.span.offset);
+    }
+
+    for lint in &lints {
+        eprint!(
+            "{}",
+            stones::diagnostic::render_warning(
+                &lint.message,
+                lint.span,
+                lint.hint.as_deref(),
+                filename,
+                &source
+            )
+        );
+    }
+}
+
+fn expand(args: ExpandArgs) {
+    let Some(filename) = args.filename.as_ref() else {
+        eprintln!("stones expand: missing filename");
+        std::process::exit(2);
+    };
+
+    let source = read_source(filename);
+    let (ast, field) = match stones::parse(&source) {
+        Ok(parsed) => parsed,
+        Err(err) => fail(&err, filename, &source, EXIT_PARSE),
+    };
+    // the program's output would get mixed up with the expansion
+    let mut vm = Vm::with_io(stones::compile(&ast), std::io::stdin(), std::io::sink());
+    match (args.field_file.as_ref(), field) {
+        (Some(path), _) => vm.set_field(read_field(path)),
+        (None, Some(field)) => vm.set_field(field),
+        (None, None) => {}
+    }
+
+    let expansions = stones::expand::expand(&ast, &mut vm, args.limit);
+    if args.desugar {
+        match stones::expand::desugar(&source, &ast, &expansions) {
+            Ok(desugared) => print!("{desugared}"),
+            Err(err) => fail(&err, filename, &source, 1),
+        }
+        return;
+    }
+
+    let mut from_run = false;
+    for expansion in &expansions {
+        let span = expansion.command.span();
+        let location = format!("{}:{}", span.line, span.column);
+        let written = Command::from(expansion.command).to_string();
+        let ways = match &expansion.expanded {
+            Expanded::Static(ways) => ways,
+            Expanded::Ran(ways) => {
+                from_run = true;
+                ways
+            }
+            Expanded::NotReached => {
+                println!("{location:<8}{written:<24}not reached");
+                continue;
+            }
+        };
+        let mark = if matches!(expansion.expanded, Expanded::Ran(_)) {
+            "*"
+        } else {
+            ""
+        };
+
+        for (i, ran) in ways.iter().enumerate() {
+            let (location, written) = match i {
+                0 => (location.as_str(), format!("{written}{mark}")),
+                _ => ("", String::from("or")),
+            };
+            if ran.is_empty() {
+                println!("{location:<8}{written:<24}blocked");
+            }
+            for (j, (command, opcode)) in ran.iter().enumerate() {
+                let (location, written) = match j {
+                    0 => (location, written.as_str()),
+                    _ => ("", ""),
+                };
+                println!(
+                    "{location:<8}{written:<24}{:<28}{opcode:?}",
+                    command.to_string()
+                );
+            }
+        }
+    }
+
+    if from_run {
+        println!();
+        println!("* the field here depends on the path taken, so this is from running the program");
     }
-    result.unwrap();
 }
@@ -0,0 +1,258 @@
+use std::io::Write;
+
+use crate::{
+    field::Field,
+    trace::{json_string, json_value, Event, Tracer},
+    vm::Operation,
+};
+
+// a self-contained html page with a scrubber over the field after every command
+pub struct HtmlRecorder<W> {
+    output: W,
+    title: String,
+    source: String,
+    operation: Option<Operation>,
+    // json objects, the first being the field before anything ran
+    frames: Vec<String>,
+    // the stack, if it changed since the last frame. frames without one keep the last stack
+    stack: Option<String>,
+    printed: String,
+}
+
+impl<W: Write> HtmlRecorder<W> {
+    pub fn new(output: W, title: &str, source: &str, field: &Field) -> HtmlRecorder<W> {
+        HtmlRecorder {
+            output,
+            title: title.to_string(),
+            source: source.to_string(),
+            operation: None,
+            frames: vec![frame("start", None, field, "") + r#","stack":[]}"#],
+            stack: None,
+            printed: String::new(),
+        }
+    }
+
+    pub fn finish(mut self) -> std::io::Result<W> {
+        // each placeholder shows up once, in this order. < is escaped so the program can't
+        // end the script early with </script>
+        let (head, rest) = PAGE.split_once("{title}").unwrap();
+        let (body, rest) = rest.split_once("{source}").unwrap();
+        let (script, tail) = rest.split_once("{frames}").unwrap();
+        write!(
+            self.output,
+            "{head}{}{body}{}{script}[\n{}\n]{tail}",
+            html_escape(&self.title),
+            json_string(&self.source).replace('<', "\\u003c"),
+            self.frames.join(",\n").replace('<', "\\u003c"),
+        )?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+impl<W: Write> Tracer for HtmlRecorder<W> {
+    fn trace(&mut self, event: &Event<'_>) {
+        match event {
+            Event::OperationStarted { operation, .. } => self.operation = Some(**operation),
+
+            Event::OutputWritten { text } => self.printed.push_str(text),
+
+            Event::StackChanged { stack } => {
+                let stack = stack.iter().map(json_value).collect::<Vec<_>>();
+                self.stack = Some(format!("[{}]", stack.join(",")));
+            }
+
+            Event::OpcodeExecuted {
+                command,
+                opcode,
+                field,
+                ..
+            } => {
+                let line = self.operation.and_then(|op| op.span).map(|span| span.line);
+                let mut frame = frame(
+                    &format!("{command}: {opcode:?}"),
+                    line,
+                    field,
+                    &std::mem::take(&mut self.printed),
+                );
+                if let Some(stack) = self.stack.take() {
+                    frame.push_str(&format!(r#","stack":{stack}"#));
+                }
+                frame.push('}');
+                self.frames.push(frame);
+            }
+
+            _ => {}
+        }
+    }
+}
+
+// everything but the stack and the closing brace. output is only what this frame printed,
+// the page adds up the frames before it
+fn frame(caption: &str, line: Option<usize>, field: &Field, output: &str) -> String {
+    let moved = field
+        .moved()
+        .iter()
+        .map(|stone| stone.letter())
+        .collect::<String>();
+    format!(
+        r#"{{"caption":{},"line":{},"field":{},"moved":{},"output":{}"#,
+        json_string(caption),
+        line.map_or(String::from("null"), |line| line.to_string()),
+        json_string(&field.to_string()),
+        json_string(&moved),
+        json_string(output),
+    )
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body { font-family: sans-serif; margin: 1em; background: #1e1e1e; color: #ddd; }
+pre { font-family: monospace; margin: 0; }
+#panes { display: flex; gap: 2em; align-items: flex-start; }
+#source { border-right: 1px solid #555; padding-right: 1em; }
+#source .current { background: #555; }
+#field { font-size: 1.5em; line-height: 1.2; }
+#field .moved { outline: 2px solid #fff; }
+.r { color: #e53935; } .o { color: #fb8c00; } .y { color: #fdd835; }
+.g { color: #43a047; } .b { color: #1e88e5; } .p { color: #8e24aa; } .e { color: #555; }
+#controls { margin: 1em 0; }
+#scrubber { width: 40em; max-width: 100%; }
+</style>
+</head>
+<body>
+<h3 id="title"></h3>
+<div id="controls">
+<button id="prev">&larr;</button>
+<button id="play">play</button>
+<button id="next">&rarr;</button>
+<input id="scrubber" type="range" min="0" value="0">
+<span id="counter"></span>
+</div>
+<div id="panes">
+<pre id="source"></pre>
+<div>
+<p id="caption"></p>
+<pre id="field"></pre>
+<p>stack: <code id="stack"></code></p>
+<p>output:</p>
+<pre id="output"></pre>
+</div>
+</div>
+<script>
+const source = {source};
+const frames = {frames};
+
+const $ = (id) => document.getElementById(id);
+$("title").textContent = document.title;
+const scrubber = $("scrubber");
+scrubber.max = frames.length - 1;
+
+const lines = source.split("\n");
+$("source").innerHTML = "";
+const lineElements = lines.map((text, i) => {
+  const line = document.createElement("div");
+  line.textContent = String(i + 1).padStart(String(lines.length).length) + " " + text;
+  $("source").appendChild(line);
+  return line;
+});
+
+function show(index) {
+  const frame = frames[index];
+  scrubber.value = index;
+  $("counter").textContent = (index + 1) + " / " + frames.length;
+  $("caption").textContent = frame.caption;
+  const stack = frames.slice(0, index + 1).reverse().find((f) => f.stack !== undefined).stack;
+  $("stack").textContent = JSON.stringify(stack);
+  $("output").textContent = frames.slice(0, index + 1).map((f) => f.output).join("");
+
+  lineElements.forEach((line, i) => line.classList.toggle("current", frame.line === i + 1));
+  if (frame.line !== null && lineElements[frame.line - 1]) {
+    lineElements[frame.line - 1].scrollIntoView({ block: "nearest" });
+  }
+
+  $("field").innerHTML = "";
+  for (const c of frame.field) {
+    const cell = document.createElement("span");
+    cell.textContent = c;
+    if (c !== "\n") {
+      cell.className = c === "." ? "e" : c;
+      if (frame.moved.includes(c)) {
+        cell.classList.add("moved");
+      }
+    }
+    $("field").appendChild(cell);
+  }
+}
+
+let timer = null;
+function stop() {
+  clearInterval(timer);
+  timer = null;
+  $("play").textContent = "play";
+}
+const step = (by) => show(Math.min(Math.max(Number(scrubber.value) + by, 0), frames.length - 1));
+
+scrubber.oninput = () => { stop(); show(Number(scrubber.value)); };
+$("prev").onclick = () => { stop(); step(-1); };
+$("next").onclick = () => { stop(); step(1); };
+$("play").onclick = () => {
+  if (timer !== null) {
+    stop();
+    return;
+  }
+  $("play").textContent = "pause";
+  timer = setInterval(() => {
+    if (Number(scrubber.value) + 1 >= frames.length) {
+      stop();
+    } else {
+      step(1);
+    }
+  }, 400);
+};
+document.onkeydown = (event) => {
+  if (event.key === "ArrowLeft") { stop(); step(-1); }
+  if (event.key === "ArrowRight") { stop(); step(1); }
+};
+
+show(0);
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::Vm;
+
+    #[test]
+    fn frames() {
+        let source = "red right two\nblue up\nred left three purple up purple right\n";
+        let program = crate::compile(&crate::parse(source).unwrap().0);
+        let mut vm = Vm::with_io(program, std::io::empty(), std::io::sink());
+        let mut recorder = HtmlRecorder::new(Vec::new(), "test", source, vm.field());
+        vm.run(&mut recorder).unwrap();
+
+        assert_eq!(recorder.frames.len(), 6);
+        assert!(recorder.frames[1].contains(r#""line":1,"#));
+        assert!(recorder.frames[1].ends_with(r#""output":"","stack":[7]}"#));
+        assert!(recorder.frames[2].ends_with(r#""output":"7","stack":[]}"#));
+        // purple right doesn't touch the stack
+        assert!(recorder.frames[5].ends_with(r#""output":""}"#));
+
+        let page = String::from_utf8(recorder.finish().unwrap()).unwrap();
+        assert!(page.contains(r#"const source = "red right two\nblue up\nred left three"#));
+        assert!(!page.contains("{frames}"));
+    }
+}
@@ -0,0 +1,289 @@
+use std::io::Write;
+
+use crate::{
+    command::{Command, Stone},
+    field::Field,
+    vm::{Opcode, Operation},
+    Value,
+};
+
+#[derive(Debug)]
+pub enum Event<'a> {
+    OperationStarted {
+        ip: usize,
+        operation: &'a Operation,
+    },
+    // a lighter stone was pushed out of the way, so its command runs first. the
+    // field is after the lighter stone moved but before the heavier one follows it
+    CommandPushed {
+        command: Command,
+        by: Command,
+        field: &'a Field,
+    },
+    CommandBlocked {
+        command: Command,
+    },
+    CommandPartiallyBlocked {
+        command: Command,
+        old_magnitude: usize,
+        new_magnitude: usize,
+    },
+    OpcodeExecuted {
+        ip: usize,
+        command: Command,
+        opcode: Opcode,
+        field: &'a Field,
+        stack: &'a [Value],
+    },
+    // just before the OpcodeExecuted of a command that pushed or popped anything
+    StackChanged {
+        stack: &'a [Value],
+    },
+    // printed by blue up or blue left, before the command's OpcodeExecuted
+    OutputWritten {
+        text: &'a str,
+    },
+}
+
+pub trait Tracer {
+    fn trace(&mut self, event: &Event<'_>);
+}
+
+impl Tracer for () {
+    fn trace(&mut self, _: &Event<'_>) {}
+}
+
+// what --print-operation, --print-field, and --print-stack show
+#[derive(Debug, Default)]
+pub struct PrintTracer {
+    pub print_op: bool,
+    pub print_field: bool,
+    pub print_stack: bool,
+    // draw the field with terminal colors instead of plain letters
+    pub color: bool,
+}
+
+impl Tracer for PrintTracer {
+    fn trace(&mut self, event: &Event<'_>) {
+        match event {
+            Event::CommandPushed { command, by, .. } if self.print_op => {
+                println!("pushed {by:?} > {:?}", command.color);
+            }
+
+            Event::CommandBlocked { command } if self.print_op => {
+                println!("blocked {command:?}");
+            }
+
+            Event::CommandPartiallyBlocked {
+                command,
+                new_magnitude,
+                ..
+            } if self.print_op => {
+                let new_cmd = command.change_magnitude(*new_magnitude);
+                println!("partially blocked {command:?} -> {new_cmd:?}");
+            }
+
+            Event::OpcodeExecuted {
+                ip,
+                command,
+                opcode,
+                field,
+                stack,
+            } => {
+                // numbered from 1, like before there were tracers
+                if self.print_op {
+                    crate::print_command_opcode(ip + 1, command, *opcode);
+                }
+                if self.print_field && self.color {
+                    println!("{}", field.ansi());
+                } else if self.print_field {
+                    println!("{field}");
+                }
+                if self.print_stack {
+                    for value in *stack {
+                        println!("{value:?}");
+                    }
+                }
+            }
+
+            _ => {}
+        }
+    }
+}
+
+impl<A: Tracer, B: Tracer> Tracer for (A, B) {
+    fn trace(&mut self, event: &Event<'_>) {
+        self.0.trace(event);
+        self.1.trace(event);
+    }
+}
+
+impl<T: Tracer> Tracer for Option<T> {
+    fn trace(&mut self, event: &Event<'_>) {
+        if let Some(tracer) = self {
+            tracer.trace(event);
+        }
+    }
+}
+
+// one json object per executed command, with the stack after it
+pub struct JsonTracer<W> {
+    output: W,
+    operation: Option<Operation>,
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(output: W) -> JsonTracer<W> {
+        JsonTracer {
+            output,
+            operation: None,
+            error: None,
+        }
+    }
+
+    // returns the first error hit while writing, since tracing itself can't fail
+    pub fn finish(mut self) -> std::io::Result<W> {
+        match self.error {
+            Some(err) => Err(err),
+            None => {
+                self.output.flush()?;
+                Ok(self.output)
+            }
+        }
+    }
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn trace(&mut self, event: &Event<'_>) {
+        match event {
+            Event::OperationStarted { operation, .. } => self.operation = Some(**operation),
+
+            Event::OpcodeExecuted {
+                ip,
+                command,
+                opcode,
+                field,
+                stack,
+            } => {
+                let source = self.operation.as_ref();
+                let mut json = format!(
+                    r#"{{"ip":{ip},"command":{},"source":{},"line":{},"side_effect":{},"opcode":{}"#,
+                    json_string(&command.to_string()),
+                    source.map_or(String::from("null"), |op| json_string(
+                        &op.command.to_string()
+                    )),
+                    source
+                        .and_then(|op| op.span)
+                        .map_or(String::from("null"), |span| span.line.to_string()),
+                    command.side_effect,
+                    json_opcode(*opcode),
+                );
+
+                json.push_str(r#","stones":{"#);
+                for (i, stone) in Stone::ALL.iter().enumerate() {
+                    if i != 0 {
+                        json.push(',');
+                    }
+                    json.push_str(&format!(r#""{stone}":"#));
+                    match field.position(*stone) {
+                        Some((row, col)) => {
+                            json.push_str(&format!(r#"{{"row":{row},"col":{col}}}"#))
+                        }
+                        None => json.push_str("null"),
+                    }
+                }
+                json.push('}');
+
+                let stack = stack.iter().map(json_value).collect::<Vec<_>>();
+                json.push_str(&format!(r#","stack":[{}]}}"#, stack.join(",")));
+
+                if self.error.is_none() {
+                    if let Err(err) = writeln!(self.output, "{json}") {
+                        self.error = Some(err);
+                    }
+                }
+            }
+
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+pub(crate) fn json_value(value: &Value) -> String {
+    match value {
+        Value::Num(num) => num.to_string(),
+        Value::Bool(bool) => bool.to_string(),
+        Value::Arr(arr) => format!(
+            "[{}]",
+            arr.iter().map(json_value).collect::<Vec<_>>().join(",")
+        ),
+    }
+}
+
+// like serde's externally tagged enums: "Dup", {"PushNumber":7}, {"Math":"Add"}
+fn json_opcode(opcode: Opcode) -> String {
+    match opcode {
+        Opcode::PushNumber(num) => format!(r#"{{"PushNumber":{num}}}"#),
+        Opcode::PushBool(bool) => format!(r#"{{"PushBool":{bool}}}"#),
+        Opcode::Comparison(comparison) => format!(r#"{{"Comparison":"{comparison:?}"}}"#),
+        Opcode::Math(math) => format!(r#"{{"Math":"{math:?}"}}"#),
+        Opcode::JumpFalse(offset) => format!(r#"{{"JumpFalse":{offset}}}"#),
+        Opcode::JumpForward(offset) => format!(r#"{{"JumpForward":{offset}}}"#),
+        Opcode::JumpBackward(offset) => format!(r#"{{"JumpBackward":{offset}}}"#),
+        opcode => format!(r#""{opcode:?}""#),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::Vm;
+
+    #[test]
+    fn json_lines() {
+        let program = crate::compile(&crate::parse("red right two\nblue up\n").unwrap().0);
+        let mut vm = Vm::with_io(program, std::io::empty(), std::io::sink());
+        let mut tracer = JsonTracer::new(Vec::new());
+        vm.run(&mut tracer).unwrap();
+
+        let json = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let stones = r#""stones":{"red":{"row":2,"col":4},"orange":{"row":0,"col":6},"yellow":{"row":4,"col":4},"green":{"row":2,"col":8},"blue":{"#;
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"ip":0,"command":"red right two","source":"red right two","line":1,"side_effect":false,"opcode":{{"PushNumber":7}},{stones}"row":0,"col":0}},"purple":{{"row":4,"col":10}}}},"stack":[7]}}
+{{"ip":1,"command":"blue up","source":"blue up","line":2,"side_effect":false,"opcode":"Print",{stones}"row":5,"col":0}},"purple":{{"row":4,"col":10}}}},"stack":[]}}
+"#
+            )
+        );
+
+        // every line has the stack, even when the command didn't change it
+        let source = "red left three purple up red up one purple right blue up";
+        let program = crate::compile(&crate::parse(source).unwrap().0);
+        let mut vm = Vm::with_io(program, std::io::empty(), std::io::sink());
+        let mut tracer = JsonTracer::new(Vec::new());
+        vm.run(&mut tracer).unwrap();
+
+        let json = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let lines = json.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[3].contains(r#""command":"purple right""#));
+        assert!(lines[3].ends_with(r#""stack":[0]}"#));
+    }
+}
@@ -0,0 +1,426 @@
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers},
+    queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType},
+};
+use stones::{
+    field::Field,
+    trace::{Event, Tracer},
+    vm::Vm,
+    Span, Value,
+};
+
+const HELP: &str = "space play/pause  s step  + faster  - slower  q quit";
+
+// one picture of the program, either a stone being pushed or a command running
+#[derive(Clone)]
+struct Frame {
+    caption: String,
+    span: Option<Span>,
+    field: Field,
+    stack: Vec<Value>,
+}
+
+// turns the events from one operation into frames
+struct Recorder {
+    frames: Vec<Frame>,
+    span: Option<Span>,
+    field: Field,
+    stack: Vec<Value>,
+}
+
+impl Recorder {
+    fn new(field: &Field, stack: &[Value]) -> Recorder {
+        Recorder {
+            frames: Vec::new(),
+            span: None,
+            field: field.clone(),
+            stack: stack.to_vec(),
+        }
+    }
+
+    fn frame(&mut self, caption: String) {
+        self.frames.push(Frame {
+            caption,
+            span: self.span,
+            field: self.field.clone(),
+            stack: self.stack.clone(),
+        });
+    }
+}
+
+impl Tracer for Recorder {
+    fn trace(&mut self, event: &Event<'_>) {
+        match event {
+            Event::OperationStarted { operation, .. } => self.span = operation.span,
+
+            Event::CommandPushed { command, by, field } => {
+                self.field = (*field).clone();
+                self.frame(format!("{by} pushes {}", command.color));
+            }
+
+            Event::CommandBlocked { command } => self.frame(format!("{command} is blocked")),
+
+            Event::StackChanged { stack } => self.stack = stack.to_vec(),
+
+            Event::OpcodeExecuted {
+                command,
+                opcode,
+                field,
+                ..
+            } => {
+                self.field = (*field).clone();
+                self.frame(format!("{command}: {opcode:?}"));
+            }
+
+            _ => {}
+        }
+    }
+}
+
+// restores the terminal even if drawing fails partway through
+struct Screen;
+
+impl Screen {
+    fn enter() -> std::io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(
+            std::io::stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide
+        )?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(
+            std::io::stdout(),
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+pub struct Tui<R> {
+    vm: Vm<R, Vec<u8>>,
+    source: String,
+    filename: String,
+    frames: VecDeque<Frame>,
+    shown: Frame,
+    playing: bool,
+    delay: Duration,
+    status: String,
+}
+
+impl<R: Read> Tui<R> {
+    pub fn new(vm: Vm<R, Vec<u8>>, source: String, filename: String) -> Tui<R> {
+        let shown = Frame {
+            caption: String::from("start"),
+            span: vm.program().first().and_then(|operation| operation.span),
+            field: vm.field().clone(),
+            stack: Vec::new(),
+        };
+
+        Tui {
+            vm,
+            source,
+            filename,
+            frames: VecDeque::new(),
+            shown,
+            playing: false,
+            delay: Duration::from_millis(500),
+            status: String::from("paused"),
+        }
+    }
+
+    pub fn run(&mut self) -> std::io::Result<()> {
+        let _screen = Screen::enter()?;
+        let mut stdout = std::io::stdout();
+        let mut last_frame = Instant::now();
+
+        loop {
+            self.draw(&mut stdout)?;
+
+            let timeout = if self.playing {
+                self.delay.saturating_sub(last_frame.elapsed())
+            } else {
+                Duration::MAX
+            };
+
+            if !event::poll(timeout)? {
+                self.advance();
+                last_frame = Instant::now();
+                continue;
+            }
+
+            let TermEvent::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                KeyCode::Char(' ') => {
+                    self.playing = !self.playing && !self.finished();
+                    last_frame = Instant::now();
+                }
+                KeyCode::Char('s') | KeyCode::Char('n') | KeyCode::Right => {
+                    self.playing = false;
+                    self.advance();
+                }
+                KeyCode::Char('+') | KeyCode::Char('=') => {
+                    self.delay = (self.delay / 2).max(Duration::from_millis(15));
+                }
+                KeyCode::Char('-') => {
+                    self.delay = (self.delay * 2).min(Duration::from_secs(4));
+                }
+                _ => {}
+            }
+
+            if !self.finished() {
+                self.status = String::from(if self.playing { "playing" } else { "paused" });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finished(&self) -> bool {
+        self.frames.is_empty() && (self.vm.halted() || self.status.starts_with("error"))
+    }
+
+    // shows the next frame, running the next operation if there aren't any left
+    fn advance(&mut self) {
+        while self.frames.is_empty() && !self.finished() {
+            let mut recorder = Recorder::new(self.vm.field(), self.vm.stack());
+            match self.vm.step_traced(&mut recorder) {
+                Ok(_) => {}
+                Err(stones::Error::Quine) => {
+                    let _ = self.vm.output_mut().write_all(self.source.as_bytes());
+                }
+                Err(err) => {
+                    self.status = format!("error: {err}");
+                    self.playing = false;
+                }
+            }
+            self.frames.extend(recorder.frames);
+        }
+
+        if let Some(frame) = self.frames.pop_front() {
+            self.shown = frame;
+        }
+
+        if self.finished() {
+            self.playing = false;
+            if self.vm.halted() {
+                self.status = String::from("halted");
+            }
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let (cols, rows) = terminal::size()?;
+        let (cols, rows) = (cols as usize, rows as usize);
+
+        let right_width = self.shown.field.width().max(32);
+        let left_width = cols.saturating_sub(right_width + 3).max(20);
+        let right = left_width as u16 + 2;
+        let body = rows.saturating_sub(2);
+
+        queue!(out, Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        queue!(
+            out,
+            SetAttribute(Attribute::Bold),
+            Print(truncate(
+                &format!(
+                    "{}  [{}]  {}ms per frame",
+                    self.filename,
+                    self.status,
+                    self.delay.as_millis()
+                ),
+                cols
+            )),
+            SetAttribute(Attribute::Reset)
+        )?;
+
+        self.draw_source(out, left_width, body)?;
+
+        let mut pane = vec![truncate(&self.shown.caption, right_width), String::new()];
+        // the colors are escape codes, so the field isn't truncated
+        pane.extend(
+            self.shown
+                .field
+                .ansi()
+                .to_string()
+                .lines()
+                .map(String::from),
+        );
+        pane.push(String::new());
+
+        let stack = self
+            .shown
+            .stack
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        pane.push(truncate(&format!("stack: {stack}"), right_width));
+        if let Some(array) = self.vm.array_in_progress() {
+            pane.push(truncate(&format!("array: {array:?}"), right_width));
+        }
+        pane.push(String::new());
+
+        pane.push(String::from("output:"));
+        let output = String::from_utf8_lossy(self.vm.output());
+        let lines = output.lines().collect::<Vec<_>>();
+        let room = body.saturating_sub(pane.len());
+        for output_line in &lines[lines.len().saturating_sub(room)..] {
+            pane.push(truncate(output_line, right_width));
+        }
+
+        for (row, text) in pane.iter().take(body).enumerate() {
+            queue!(out, cursor::MoveTo(right, row as u16 + 1), Print(text))?;
+        }
+
+        queue!(
+            out,
+            cursor::MoveTo(0, rows.saturating_sub(1) as u16),
+            SetAttribute(Attribute::Dim),
+            Print(truncate(HELP, cols)),
+            SetAttribute(Attribute::Reset)
+        )?;
+        out.flush()
+    }
+
+    // the source with the current command in reverse video, scrolled to keep it on screen
+    fn draw_source(
+        &self,
+        out: &mut impl Write,
+        width: usize,
+        height: usize,
+    ) -> std::io::Result<()> {
+        let lines = self.source.lines().collect::<Vec<_>>();
+        let gutter = lines.len().to_string().len();
+        let current = self.shown.span.map_or(0, |span| span.line - 1);
+        let first = current
+            .saturating_sub(height / 2)
+            .min(lines.len().saturating_sub(height));
+
+        for (i, source_line) in lines.iter().enumerate().skip(first).take(height) {
+            // tabs would throw off the columns in the spans
+            let text = truncate(
+                &format!("{:gutter$} {}", i + 1, source_line.replace('\t', " ")),
+                width,
+            );
+            queue!(out, cursor::MoveTo(0, (i - first + 1) as u16))?;
+
+            match self.shown.span {
+                Some(span) if span.line == i + 1 => {
+                    let highlighted = highlighted(source_line, span);
+                    let (start, end) =
+                        (gutter + 1 + highlighted.start, gutter + 1 + highlighted.end);
+                    let before = text.chars().take(start).collect::<String>();
+                    let during = text
+                        .chars()
+                        .skip(start)
+                        .take(end - start)
+                        .collect::<String>();
+                    let after = text.chars().skip(end).collect::<String>();
+                    queue!(
+                        out,
+                        Print(before),
+                        SetAttribute(Attribute::Reverse),
+                        Print(during),
+                        SetAttribute(Attribute::Reset),
+                        Print(after)
+                    )?;
+                }
+                _ => queue!(out, Print(text))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// the characters of a line that a span covers. columns count characters but lengths count
+// bytes, and a span can run on past the end of the line
+fn highlighted(line: &str, span: Span) -> Range<usize> {
+    let start = span.column - 1;
+    let text = match line.char_indices().nth(start) {
+        Some((offset, _)) => &line[offset..],
+        None => "",
+    };
+    let len = text
+        .char_indices()
+        .take_while(|(offset, _)| *offset < span.len)
+        .count();
+    start..start + len
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stones::command::Stone;
+
+    #[test]
+    fn highlights_by_character() {
+        let source = "ünï red up one\nblue up";
+        let (ast, _) = stones::parse(source).unwrap();
+        let line = source.lines().next().unwrap();
+        let columns = highlighted(line, ast[0].span());
+        assert_eq!(
+            line.chars()
+                .skip(columns.start)
+                .take(columns.len())
+                .collect::<String>(),
+            "red up one"
+        );
+    }
+
+    #[test]
+    fn push_frames() {
+        let (ast, field) = stones::parse("#field\nb.o\nr.y\n.g.\np..\n#end\nblue down\n").unwrap();
+        let mut vm = Vm::with_io(stones::compile(&ast), "7\n".as_bytes(), Vec::new());
+        vm.set_field(field.unwrap());
+
+        // blue pushes red out of the way, so red's 1 is pushed before blue reads input
+        let mut recorder = Recorder::new(vm.field(), vm.stack());
+        vm.step_traced(&mut recorder).unwrap();
+        let captions = recorder
+            .frames
+            .iter()
+            .map(|frame| frame.caption.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            captions,
+            [
+                "blue down pushes red",
+                "red down one (side effect): PushNumber(1)",
+                "blue down: Input"
+            ]
+        );
+        assert_eq!(recorder.frames[0].field.position(Stone::Red), Some((2, 0)));
+        assert_eq!(recorder.frames[0].field.position(Stone::Blue), Some((0, 0)));
+        assert_eq!(recorder.frames[2].stack.len(), 2);
+    }
+}
@@ -1,4 +1,12 @@
-use crate::{command::Command, field::Field, Error, Value};
+use std::io::{Read, Stdin, Stdout, Write};
+
+use crate::{
+    analysis::Resolved,
+    command::{Command, Stone},
+    field::Field,
+    trace::{Event, Tracer},
+    Error, Span, Value,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Comparison {
@@ -35,7 +43,7 @@ pub enum Opcode {
     Printc,
     Swap,
     JumpFalse(usize),    // always forward: head of if/while
-    JumpForward(usize),  // else command of if
+    JumpForward(usize),  // else and end of if
     JumpBackward(usize), // end of while
     Die,
 }
@@ -44,6 +52,7 @@ pub enum Opcode {
 pub struct Operation {
     pub command: Command,
     pub opcode: Opcode,
+    pub span: Option<Span>,
 }
 
 impl Operation {
@@ -51,6 +60,7 @@ impl Operation {
         Operation {
             command: Command::empty(),
             opcode: Opcode::Die,
+            span: None,
         }
     }
 
@@ -58,217 +68,750 @@ impl Operation {
         Operation {
             opcode: command.get_opcode().unwrap(),
             command,
+            span: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StepResult {
+    Executed {
+        ip: usize,
+        operation: Operation,
+        // what actually ran after the field moved, including side effects. a blocked purple
+        // command still jumps, but isn't included
+        commands: Vec<(Command, Opcode)>,
+    },
+    Halted,
+}
+
+impl StepResult {
+    // the command didn't move its stone at all
+    pub fn blocked(&self) -> bool {
+        match self {
+            StepResult::Executed { commands, .. } => {
+                commands.iter().all(|(command, _)| command.side_effect)
+            }
+            StepResult::Halted => false,
+        }
+    }
+
+    // the command moved its stone, but not as far as it was written
+    pub fn partially_blocked(&self) -> bool {
+        match self {
+            StepResult::Executed {
+                operation,
+                commands,
+                ..
+            } => commands.iter().any(|(command, _)| {
+                !command.side_effect && command.magnitude() < operation.command.magnitude()
+            }),
+            StepResult::Halted => false,
         }
     }
 }
 
 #[derive(Debug)]
-pub struct Vm {
+pub struct Vm<R = Stdin, W = Stdout> {
     stack: Vec<Value>,
     program: Vec<Operation>,
     ip: usize,
     array_in_progress: Option<Vec<Value>>,
-    field: Field<12, 6>,
+    field: Field,
+    // what each operation does to the field, where it's known ahead of time
+    resolved: Vec<Option<Resolved>>,
+    // whether the stack changed since the last StackChanged event
+    stack_changed: bool,
+    input: R,
+    output: W,
 }
 
 impl Vm {
     pub fn new(program: Vec<Operation>) -> Vm {
+        Vm::with_io(program, std::io::stdin(), std::io::stdout())
+    }
+}
+
+impl<R: Read, W: Write> Vm<R, W> {
+    pub fn with_io(program: Vec<Operation>, input: R, output: W) -> Vm<R, W> {
         Vm {
             stack: Vec::new(),
             program,
             ip: 0,
             array_in_progress: None,
             field: Field::new(),
+            resolved: Vec::new(),
+            stack_changed: false,
+            input,
+            output,
         }
     }
 
+    // keeps the stack, field, and array in progress
+    pub fn load(&mut self, program: Vec<Operation>) {
+        self.program = program;
+        self.ip = 0;
+        self.resolved.clear();
+    }
+
+    // skips moving the stones for operations whose commands are already known, from an
+    // Analysis of the program starting from the current field. tracers don't see stones
+    // being pushed or blocked for those operations
+    pub fn set_resolved(&mut self, resolved: Vec<Option<Resolved>>) {
+        self.resolved = resolved;
+    }
+
     fn push(&mut self, value: Value) {
         self.stack.push(value);
+        self.stack_changed = true;
+    }
+
+    pub(crate) fn pop(&mut self) -> Result<Value, Error> {
+        let value = self.stack.pop().ok_or(Error::StackUnderflow)?;
+        self.stack_changed = true;
+        Ok(value)
     }
 
-    fn pop(&mut self) -> Result<Value, Error> {
-        self.stack.pop().ok_or(Error::StackUnderflow)
+    // StackChanged comes before the OpcodeExecuted it belongs to, and only if the stack
+    // changed since the last one
+    pub(crate) fn trace_opcode(
+        &mut self,
+        ip: usize,
+        command: Command,
+        opcode: Opcode,
+        tracer: &mut dyn Tracer,
+    ) {
+        if std::mem::take(&mut self.stack_changed) {
+            tracer.trace(&Event::StackChanged { stack: &self.stack });
+        }
+        tracer.trace(&Event::OpcodeExecuted {
+            ip,
+            command,
+            opcode,
+            field: &self.field,
+            stack: &self.stack,
+        });
+    }
+
+    // reads a byte at a time so nothing past the newline is taken from the input
+    #[allow(clippy::unbuffered_bytes)]
+    fn read_line(&mut self) -> Result<String, Error> {
+        let mut line = Vec::new();
+        for byte in (&mut self.input).bytes() {
+            match byte? {
+                b'\n' => break,
+                byte => line.push(byte),
+            }
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
     }
 
     fn peek(&mut self, depth: usize) -> Result<&Value, Error> {
         self.stack
-            .get(self.stack.len() - depth - 1)
+            .len()
+            .checked_sub(depth + 1)
+            .and_then(|idx| self.stack.get(idx))
             .ok_or(Error::StackUnderflow)
     }
 
-    pub fn run(
-        &mut self,
-        print_op: bool,
-        print_field: bool,
-        print_stack: bool,
-    ) -> Result<(), Error> {
-        loop {
-            if self.ip >= self.program.len() {
-                break;
-            }
+    pub fn run(&mut self, tracer: &mut dyn Tracer) -> Result<(), Error> {
+        while self.step_inner(tracer)? != StepResult::Halted {}
+        self.output.flush()?;
+        Ok(())
+    }
 
-            let operation = self.program[self.ip];
-            self.ip += 1;
-            let Operation { command, opcode } = operation;
+    // returns how many operations were executed, fewer than asked if the program ended
+    pub fn run_for(&mut self, steps: usize) -> Result<usize, Error> {
+        let mut taken = 0;
+        while taken < steps && self.step_inner(&mut ())? != StepResult::Halted {
+            taken += 1;
+        }
+        self.output.flush()?;
+        Ok(taken)
+    }
 
-            let commands = self.field.commands_for(command, print_op);
-            for command in commands {
-                let opcode = command.get_opcode().unwrap_or(opcode);
-                if print_op {
-                    crate::print_command_opcode(self.ip, &command, opcode);
-                }
+    pub fn step(&mut self) -> Result<StepResult, Error> {
+        self.step_traced(&mut ())
+    }
 
-                match opcode {
-                    Opcode::PushNumber(num) => self.push(Value::Num(num)),
+    pub fn step_traced(&mut self, tracer: &mut dyn Tracer) -> Result<StepResult, Error> {
+        let result = self.step_inner(tracer)?;
+        self.output.flush()?;
+        Ok(result)
+    }
 
-                    Opcode::PushBool(bool) => self.push(Value::Bool(bool)),
+    fn step_inner(&mut self, tracer: &mut dyn Tracer) -> Result<StepResult, Error> {
+        if self.ip >= self.program.len() {
+            return Ok(StepResult::Halted);
+        }
 
-                    Opcode::StartArray => self.array_in_progress = Some(Vec::new()),
+        let ip = self.ip;
+        let operation = self.program[ip];
+        self.ip += 1;
 
-                    Opcode::PushArray => {
-                        let arr = self
-                            .array_in_progress
-                            .take()
-                            .ok_or_else(|| todo!())
-                            .unwrap();
-                        self.push(Value::Arr(arr));
-                    }
+        tracer.trace(&Event::OperationStarted {
+            ip,
+            operation: &operation,
+        });
 
-                    Opcode::EndArray => {
-                        let in_progress = self.array_in_progress.take();
-                        if let Some(mut in_progress) = in_progress {
-                            in_progress.push(self.pop()?);
-                            self.array_in_progress = Some(in_progress);
-                        } else {
-                            todo!();
-                        }
-                    }
+        // a field the analysis didn't expect moves the stones like any other
+        let commands = match self.resolved.get(ip).and_then(Option::as_ref) {
+            Some(resolved) if self.field == resolved.before => {
+                self.field.clone_from(&resolved.after);
+                resolved.commands.clone()
+            }
+            _ => self.field.commands_for(operation.command, tracer),
+        };
+        let mut executed = Vec::with_capacity(commands.len());
+        for command in commands {
+            let opcode = command.get_opcode().unwrap_or(operation.opcode);
+            executed.push((command, opcode));
+            self.execute(ip, command, opcode, tracer)?;
+            self.trace_opcode(ip, command, opcode, tracer);
+        }
 
-                    Opcode::NthArray => {
-                        let idx: i64 = self.pop()?.try_into()?;
-                        let maybe_arr = self.peek(0)?;
-                        let arr: &[Value] = maybe_arr.get_slice().ok_or(Error::TypeMismatch {
-                            wanted: "array",
-                            got: maybe_arr.type_name(),
-                        })?;
-                        let dup = arr[idx as usize].clone();
-                        self.push(dup);
-                    }
+        // purple's jump is the control flow around it, so it happens even when the stone is
+        // blocked. it's left out of the commands since the stone didn't move
+        if operation.command.color == Stone::Purple
+            && executed.iter().all(|(command, _)| command.side_effect)
+        {
+            self.execute(ip, operation.command, operation.opcode, tracer)?;
+            self.trace_opcode(ip, operation.command, operation.opcode, tracer);
+        }
 
-                    Opcode::Comparison(comparison) => {
-                        let lhs = self.pop()?;
-                        let rhs = self.pop()?;
-                        self.push(Value::Bool(match comparison {
-                            Comparison::Equal => lhs == rhs,
-                            Comparison::LessThan => lhs > rhs && lhs != rhs,
-                            Comparison::GreaterThan => lhs < rhs && lhs != rhs,
-                        }));
-                    }
+        Ok(StepResult::Executed {
+            ip,
+            operation,
+            commands: executed,
+        })
+    }
 
-                    Opcode::Quine => Err(Error::Quine)?,
-
-                    Opcode::Math(math) => {
-                        let lhs: i64 = self.pop()?.try_into()?;
-                        let rhs: i64 = self.pop()?.try_into()?;
-                        self.push(Value::Num(match math {
-                            Math::Multiply => lhs * rhs,
-                            Math::Add => lhs + rhs,
-                            Math::Subtract => lhs - rhs,
-                            Math::Divide => lhs / rhs,
-                        }));
-                    }
+    pub(crate) fn execute(
+        &mut self,
+        ip: usize,
+        command: Command,
+        opcode: Opcode,
+        tracer: &mut dyn Tracer,
+    ) -> Result<(), Error> {
+        match opcode {
+            Opcode::PushNumber(num) => self.push(Value::Num(num)),
 
-                    Opcode::Roll => {
-                        let d: i64 = self.pop()?.try_into()?;
-                        if d > 0 {
-                            let mut to_roll = Vec::new();
-                            for _ in 0..d + 1 {
-                                to_roll.push(self.pop()?);
-                            }
-                            to_roll.reverse();
-                            let top = to_roll.pop().unwrap();
-                            to_roll.insert(0, top);
-                            for elem in to_roll {
-                                self.push(elem);
-                            }
-                        }
-                    }
+            Opcode::PushBool(bool) => self.push(Value::Bool(bool)),
 
-                    Opcode::Dup => {
-                        let dup = self.pop()?;
-                        self.push(dup.clone());
-                        self.push(dup);
-                    }
+            Opcode::StartArray => self.array_in_progress = Some(Vec::new()),
 
-                    Opcode::Drop => {
-                        let _ = self.pop()?;
-                    }
+            Opcode::PushArray => {
+                let arr = self
+                    .array_in_progress
+                    .take()
+                    .ok_or(Error::NoArrayInProgress { ip, command })?;
+                self.push(Value::Arr(arr));
+            }
 
-                    Opcode::Not => {
-                        let bool = self.pop()?.is_truthy();
-                        self.push(Value::Bool(!bool));
-                    }
+            Opcode::EndArray => {
+                let in_progress = self.array_in_progress.take();
+                if let Some(mut in_progress) = in_progress {
+                    in_progress.push(self.pop()?);
+                    self.array_in_progress = Some(in_progress);
+                } else {
+                    return Err(Error::NoArrayInProgress { ip, command });
+                }
+            }
 
-                    Opcode::Print => self.pop()?.print_as_num(),
-
-                    Opcode::Input => {
-                        let mut line = String::new();
-                        std::io::stdin().read_line(&mut line)?;
-                        let line = line.trim();
-                        if let Ok(num) = line.parse() {
-                            self.push(Value::Num(num));
-                        } else if let Ok(bool) = line.parse() {
-                            self.push(Value::Bool(bool));
-                        } else {
-                            todo!();
-                        }
-                    }
+            Opcode::NthArray => {
+                let idx: i64 = self.pop()?.try_into()?;
+                let maybe_arr = self.peek(0)?;
+                let arr: &[Value] = maybe_arr.get_slice().ok_or(Error::TypeMismatch {
+                    wanted: "array",
+                    got: maybe_arr.type_name(),
+                })?;
+                let dup = usize::try_from(idx)
+                    .ok()
+                    .and_then(|i| arr.get(i))
+                    .ok_or(Error::IndexOutOfBounds {
+                        index: idx,
+                        len: arr.len(),
+                        ip,
+                        command,
+                    })?
+                    .clone();
+                self.push(dup);
+            }
 
-                    Opcode::Printc => self.pop()?.print_as_char(),
+            Opcode::Comparison(comparison) => {
+                let lhs = self.pop()?;
+                let rhs = self.pop()?;
+                self.push(Value::Bool(match comparison {
+                    Comparison::Equal => lhs == rhs,
+                    Comparison::LessThan => lhs > rhs && lhs != rhs,
+                    Comparison::GreaterThan => lhs < rhs && lhs != rhs,
+                }));
+            }
 
-                    Opcode::Swap => {
-                        let a = self.pop()?;
-                        let b = self.pop()?;
-                        self.push(a);
-                        self.push(b);
-                    }
+            Opcode::Quine => Err(Error::Quine)?,
 
-                    // always forward: head of if/while
-                    Opcode::JumpFalse(offset) => {
-                        if !self.pop()?.is_truthy() {
-                            self.ip = offset + 1;
-                        }
-                    }
+            Opcode::Math(math) => {
+                let lhs: i64 = self.pop()?.try_into()?;
+                let rhs: i64 = self.pop()?.try_into()?;
+                if math == Math::Divide && rhs == 0 {
+                    return Err(Error::DivisionByZero { ip, command });
+                }
+                let result = match math {
+                    Math::Multiply => lhs.checked_mul(rhs),
+                    Math::Add => lhs.checked_add(rhs),
+                    Math::Subtract => lhs.checked_sub(rhs),
+                    Math::Divide => lhs.checked_div(rhs),
+                };
+                self.push(Value::Num(
+                    result.ok_or(Error::IntegerOverflow { ip, command })?,
+                ));
+            }
 
-                    // else command of if
-                    Opcode::JumpForward(offset) => {
-                        self.ip = offset;
+            Opcode::Roll => {
+                let d: i64 = self.pop()?.try_into()?;
+                if d > 0 {
+                    let mut to_roll = Vec::new();
+                    for _ in 0..=d {
+                        to_roll.push(self.pop()?);
                     }
-
-                    // end of while
-                    Opcode::JumpBackward(offset) => {
-                        self.ip = offset;
+                    to_roll.reverse();
+                    let top = to_roll.pop().unwrap();
+                    to_roll.insert(0, top);
+                    for elem in to_roll {
+                        self.push(elem);
                     }
-
-                    Opcode::Die => unreachable!(),
                 }
+            }
+
+            Opcode::Dup => {
+                let dup = self.pop()?;
+                self.push(dup.clone());
+                self.push(dup);
+            }
+
+            Opcode::Drop => {
+                let _ = self.pop()?;
+            }
 
-                if print_field {
-                    println!("{:?}", self.field);
+            Opcode::Not => {
+                let bool = self.pop()?.is_truthy();
+                self.push(Value::Bool(!bool));
+            }
+
+            Opcode::Print => {
+                let text = self.pop()?.to_string();
+                self.output.write_all(text.as_bytes())?;
+                tracer.trace(&Event::OutputWritten { text: &text });
+            }
+
+            Opcode::Input => {
+                self.output.flush()?;
+                let line = self.read_line()?;
+                let line = line.trim();
+                if let Ok(num) = line.parse() {
+                    self.push(Value::Num(num));
+                } else if let Ok(bool) = line.parse() {
+                    self.push(Value::Bool(bool));
+                } else {
+                    return Err(Error::InvalidInput {
+                        input: line.to_string(),
+                        ip,
+                        command,
+                    });
                 }
+            }
 
-                if print_stack {
-                    for value in &self.stack {
-                        println!("{value:?}");
-                    }
+            Opcode::Printc => {
+                let text = self.pop()?.as_chars().to_string();
+                self.output.write_all(text.as_bytes())?;
+                tracer.trace(&Event::OutputWritten { text: &text });
+            }
+
+            Opcode::Swap => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push(a);
+                self.push(b);
+            }
+
+            // always forward: head of if/while
+            Opcode::JumpFalse(offset) => {
+                if !self.pop()?.is_truthy() {
+                    self.ip = offset;
                 }
             }
+
+            // else and end of if
+            Opcode::JumpForward(offset) => {
+                self.ip = offset;
+            }
+
+            // end of while
+            Opcode::JumpBackward(offset) => {
+                self.ip = offset;
+            }
+
+            Opcode::Die => unreachable!(),
         }
 
         Ok(())
     }
 
+    pub fn halted(&self) -> bool {
+        self.ip >= self.program.len()
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn program(&self) -> &[Operation] {
+        &self.program
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    pub fn array_in_progress(&self) -> Option<&[Value]> {
+        self.array_in_progress.as_deref()
+    }
+
     pub fn field(&self) -> &Field {
         &self.field
     }
+
+    pub fn set_field(&mut self, field: Field) {
+        self.field = field;
+    }
+
+    pub(crate) fn field_mut(&mut self) -> &mut Field {
+        &mut self.field
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+}
+
+// what verify found wrong with an operation, by its index in the program
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerifyError {
+    Die {
+        ip: usize,
+    },
+    InvalidNumber {
+        ip: usize,
+        command: Command,
+    },
+    OpcodeMismatch {
+        ip: usize,
+        command: Command,
+        opcode: Opcode,
+    },
+    JumpOutOfRange {
+        ip: usize,
+        opcode: Opcode,
+    },
+    UnmatchedLoopEnd {
+        ip: usize,
+    },
+}
+
+impl VerifyError {
+    pub fn ip(&self) -> usize {
+        match self {
+            VerifyError::Die { ip }
+            | VerifyError::InvalidNumber { ip, .. }
+            | VerifyError::OpcodeMismatch { ip, .. }
+            | VerifyError::JumpOutOfRange { ip, .. }
+            | VerifyError::UnmatchedLoopEnd { ip } => *ip,
+        }
+    }
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::Die { ip } => write!(f, "unpatched jump at {ip}"),
+            VerifyError::InvalidNumber { ip, command } => {
+                write!(f, "`{command}` at {ip} has an invalid number")
+            }
+            VerifyError::OpcodeMismatch {
+                ip,
+                command,
+                opcode,
+            } => write!(f, "`{command}` at {ip} can't be {opcode:?}"),
+            VerifyError::JumpOutOfRange { ip, opcode } => {
+                write!(f, "{opcode:?} at {ip} jumps outside the program")
+            }
+            VerifyError::UnmatchedLoopEnd { ip } => {
+                write!(f, "end of loop at {ip} doesn't match a loop head")
+            }
+        }
+    }
+}
+
+// checks a program came from something shaped like the compiler's output, so running it
+// can't hit a missing opcode. jumping to the end of the program halts it
+pub fn verify(program: &[Operation]) -> Result<(), Vec<VerifyError>> {
+    use crate::command::{Dir, EitherNumber, Stone};
+
+    let mut errors = Vec::new();
+
+    for (ip, operation) in program.iter().enumerate() {
+        let Operation {
+            command, opcode, ..
+        } = *operation;
+
+        if opcode == Opcode::Die {
+            errors.push(VerifyError::Die { ip });
+            continue;
+        }
+
+        let valid_number = match (command.color, command.number) {
+            (Stone::Red, Some(EitherNumber::Red(_))) => true,
+            (Stone::Orange, Some(EitherNumber::Orange(_))) => true,
+            (Stone::__ | Stone::Red | Stone::Orange, _) => false,
+            (_, number) => number.is_none(),
+        };
+        if !valid_number {
+            errors.push(VerifyError::InvalidNumber { ip, command });
+            continue;
+        }
+
+        let expected = match (command.color, command.dir, opcode) {
+            (Stone::Purple, Dir::Up | Dir::Left, Opcode::JumpFalse(_)) => true,
+            (Stone::Purple, Dir::Down, Opcode::JumpForward(_)) => true,
+            (Stone::Purple, Dir::Right, Opcode::JumpForward(_) | Opcode::JumpBackward(_)) => true,
+            (Stone::Purple, _, _) => false,
+            _ => command.get_opcode() == Some(opcode),
+        };
+        if !expected {
+            errors.push(VerifyError::OpcodeMismatch {
+                ip,
+                command,
+                opcode,
+            });
+            continue;
+        }
+
+        match opcode {
+            Opcode::JumpFalse(target) | Opcode::JumpForward(target)
+                if target <= ip || target > program.len() =>
+            {
+                errors.push(VerifyError::JumpOutOfRange { ip, opcode });
+            }
+
+            Opcode::JumpBackward(target) if target > ip => {
+                errors.push(VerifyError::JumpOutOfRange { ip, opcode });
+            }
+
+            // the head is the purple left at the target, and its JumpFalse leaves the loop
+            // right after this end
+            Opcode::JumpBackward(target) => {
+                let head = program[target];
+                if head.command.color != Stone::Purple
+                    || head.command.dir != Dir::Left
+                    || head.opcode != Opcode::JumpFalse(ip + 1)
+                {
+                    errors.push(VerifyError::UnmatchedLoopEnd { ip });
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(source: &str) -> Result<(), Error> {
+        run_with_input(source, "").map(|_| ())
+    }
+
+    fn run_with_input(source: &str, input: &str) -> Result<String, Error> {
+        let program = crate::compile(&crate::parse(source).unwrap().0);
+        let mut output = Vec::new();
+        Vm::with_io(program, input.as_bytes(), &mut output).run(&mut ())?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn output() {
+        assert_eq!(
+            run_with_input(include_str!("../examples/hello_world.stn"), "").unwrap(),
+            "Hello, world!\n"
+        );
+        assert_eq!(
+            run_with_input(include_str!("../examples/equality.stn"), "").unwrap(),
+            "false\ntrue\nfalse\ntrue\n"
+        );
+        assert_eq!(
+            run_with_input(include_str!("../examples/branching.stn"), "").unwrap(),
+            "!Q!Q8\n!!\n"
+        );
+        assert_eq!(
+            run_with_input(include_str!("../examples/truth-machine.stn"), "0\n").unwrap(),
+            "0"
+        );
+        assert_eq!(
+            run_with_input("blue down blue up blue down blue up", "12\ntrue\n").unwrap(),
+            "12true"
+        );
+    }
+
+    #[test]
+    fn blocked_purple_jumps() {
+        let run = |layout: &str, source: &str| {
+            let program = crate::compile(&crate::parse(source).unwrap().0);
+            let mut vm = Vm::with_io(program, std::io::empty(), Vec::new());
+            vm.set_field(Field::parse(layout).unwrap());
+            vm.run(&mut ()).unwrap();
+            (
+                vm.stack().to_vec(),
+                String::from_utf8(vm.output().clone()).unwrap(),
+            )
+        };
+
+        // purple can't move up or down, since its column is full. the condition is still
+        // popped and checked, and the true branch still skips the else
+        let column = "p.g.....\no...r...\ny.b.....";
+        let (stack, _) = run(column, "red right three purple up red up one purple right");
+        assert_eq!(stack, &[]);
+        let (stack, _) = run(column, "red left three purple up red up one purple right");
+        assert_eq!(stack, &[Value::Num(0)]);
+        let (stack, _) = run(
+            column,
+            "red left three purple up red up one purple down red up two purple right",
+        );
+        assert_eq!(stack, &[Value::Num(0)]);
+
+        // purple can't move left or right. the loop still goes back to check the condition
+        let row = "pogyb\n...r.";
+        let (stack, _) = run(
+            row,
+            "red right three red left three purple left purple right",
+        );
+        assert_eq!(stack, &[]);
+
+        // the purple right ending the true branch is blocked, and still skips the else
+        let (_, output) = run(
+            ".oy..\np....\nr....\ng...b",
+            "red left three purple up red down one blue up purple down red up one blue up purple right",
+        );
+        assert_eq!(output, "1");
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert!(matches!(
+            run("red up one red up one yellow right"),
+            Err(Error::DivisionByZero { ip: 2, .. })
+        ));
+        assert!(matches!(
+            run("orange left one"),
+            Err(Error::NoArrayInProgress { ip: 0, .. })
+        ));
+        assert!(matches!(
+            run("orange down one"),
+            Err(Error::NoArrayInProgress { ip: 0, .. })
+        ));
+        assert!(matches!(
+            run("red down one orange up one orange down one orange left one red right two orange right one"),
+            Err(Error::IndexOutOfBounds { index: 7, len: 1, ip: 5, .. })
+        ));
+        assert!(matches!(
+            run("red up one orange up one orange left one red left one red up one yellow left orange right one"),
+            Err(Error::IndexOutOfBounds { index: -2, len: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn step() {
+        let source = "red right two red down one red down one yellow up";
+        let program = crate::compile(&crate::parse(source).unwrap().0);
+        let mut vm = Vm::with_io(program, std::io::empty(), std::io::sink());
+
+        assert_eq!(vm.run_for(2).unwrap(), 2);
+        assert_eq!(vm.stack(), &[Value::Num(7), Value::Num(1)]);
+
+        let blocked = vm.step().unwrap();
+        assert!(blocked.blocked());
+        assert_eq!(vm.ip(), 3);
+
+        let StepResult::Executed { commands, .. } = vm.step().unwrap() else {
+            panic!("expected yellow up to execute");
+        };
+        assert_eq!(
+            commands
+                .iter()
+                .map(|(_, opcode)| *opcode)
+                .collect::<Vec<_>>(),
+            vec![Opcode::PushNumber(0), Opcode::Math(Math::Multiply)]
+        );
+        assert_eq!(vm.stack(), &[Value::Num(7), Value::Num(0)]);
+
+        assert_eq!(vm.step().unwrap(), StepResult::Halted);
+        assert_eq!(vm.run_for(10).unwrap(), 0);
+    }
+
+    #[test]
+    fn verifies() {
+        for example in [
+            "hello_world",
+            "equality",
+            "branching",
+            "truth-machine",
+            "quine",
+        ] {
+            let source = std::fs::read_to_string(format!("examples/{example}.stn")).unwrap();
+            let program = crate::compile(&crate::parse(&source).unwrap().0);
+            assert_eq!(verify(&program), Ok(()), "{example}");
+        }
+
+        let mut program = crate::compile(
+            &crate::parse("purple left red up one purple right blue up")
+                .unwrap()
+                .0,
+        );
+        program[1].opcode = Opcode::Dup;
+        program[2].opcode = Opcode::JumpBackward(2);
+        program[3].command.number = crate::command::Stone::Red.number_one();
+        program.push(Operation::die());
+
+        let errors = verify(&program).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|err| err.to_string()).collect::<Vec<_>>(),
+            [
+                "`red up one` at 1 can't be Dup",
+                "end of loop at 2 doesn't match a loop head",
+                "`blue up one` at 3 has an invalid number",
+                "unpatched jump at 4",
+            ]
+        );
+
+        let mut program =
+            crate::compile(&crate::parse("purple up blue up purple right").unwrap().0);
+        program[0].opcode = Opcode::JumpFalse(4);
+        assert_eq!(
+            verify(&program),
+            Err(vec![VerifyError::JumpOutOfRange {
+                ip: 0,
+                opcode: Opcode::JumpFalse(4)
+            }])
+        );
+    }
 }